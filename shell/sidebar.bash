@@ -0,0 +1,26 @@
+# sidebar の `y` で送られたディレクトリへ自動で移動するためのフック (bash)
+# ~/.bashrc に以下を追加する
+#   source /path/to/sidebar.bash
+# セッション名を変える場合は source の前に SIDEBAR_SESSION を設定する
+# (bashではプロンプトの表示は次の入力で更新される)
+# sidebarの中から開いたシェル (SIDEBAR_SUBSHELLがある) では何もしない
+
+__sidebar_sync_file="${HOME}/.sidebar/sync/bash-$$.path"
+
+__sidebar_sync_cd() {
+    [ -s "$__sidebar_sync_file" ] || return 0
+    local dir
+    dir=$(tail -n 1 "$__sidebar_sync_file")
+    : > "$__sidebar_sync_file"
+    [ -d "$dir" ] && builtin cd -- "$dir"
+    return 0
+}
+
+if [ -z "$SIDEBAR_SUBSHELL" ] && command -v sidebar > /dev/null 2>&1; then
+    mkdir -p "${HOME}/.sidebar/sync"
+    : > "$__sidebar_sync_file"
+    trap '__sidebar_sync_cd' USR1
+    trap 'rm -f "$__sidebar_sync_file"' EXIT
+    PROMPT_COMMAND="__sidebar_sync_cd${PROMPT_COMMAND:+; $PROMPT_COMMAND}"
+    ( sidebar --sync --session "${SIDEBAR_SESSION:-default}" --signal $$ >> "$__sidebar_sync_file" 2> /dev/null & )
+fi
@@ -0,0 +1,29 @@
+# sidebar の `y` で送られたディレクトリへ自動で移動するためのフック (fish)
+# ~/.config/fish/config.fish に以下を追加する
+#   source /path/to/sidebar.fish
+# セッション名を変える場合は source の前に SIDEBAR_SESSION を設定する
+# sidebarの中から開いたシェル (SIDEBAR_SUBSHELLがある) では何もしない
+
+set -g __sidebar_sync_file "$HOME/.sidebar/sync/fish-$fish_pid.path"
+
+function __sidebar_sync_cd --on-signal SIGUSR1
+    test -s $__sidebar_sync_file; or return 0
+    set -l dir (tail -n 1 $__sidebar_sync_file)
+    printf '' > $__sidebar_sync_file
+    test -d "$dir"; and builtin cd -- $dir
+    # 入力待ちのときはプロンプトを再描画
+    commandline -f repaint
+end
+
+function __sidebar_sync_cleanup --on-event fish_exit
+    rm -f $__sidebar_sync_file
+end
+
+if not set -q SIDEBAR_SUBSHELL; and command -q sidebar
+    set -l session default
+    set -q SIDEBAR_SESSION; and set session $SIDEBAR_SESSION
+    mkdir -p $HOME/.sidebar/sync
+    printf '' > $__sidebar_sync_file
+    command sidebar --sync --session $session --signal $fish_pid >> $__sidebar_sync_file 2> /dev/null &
+    disown 2> /dev/null
+end
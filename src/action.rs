@@ -0,0 +1,140 @@
+use anyhow::{anyhow, Result};
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::config::ActionConfig;
+
+// ----------------------------------------------------------------
+// config.yamlのactionsで定義した独自のコマンド
+// patternsに一致するノードに対してcommandを実行する
+//   "/" を含まないパターンはノードの名前と比べる (*.ipynb など)
+//   "/" を含むパターンはルートからの相対パスと比べる (proto/**/*.proto など)
+// ----------------------------------------------------------------
+#[derive(Debug, Clone)]
+pub struct Action {
+    pub name: String,
+    pub key: String,
+    pub command: String, // {path} などはshell::expandで置き換える
+    pub background: bool, // 終わるのを待たずに戻る
+    pub suspend: bool, // 画面を端末に戻して実行する (対話的なプログラム用)
+    pub refresh: bool, // 終わった後にツリーを読み直す
+    names: GlobSet,
+    paths: GlobSet,
+    any: bool, // パターンが無ければ全てのノードが対象
+}
+
+impl Action {
+    fn new(config: &ActionConfig) -> Result<Action> {
+        if config.command.trim().is_empty() {
+            return Err(anyhow!("command is empty"))
+        }
+        if config.background && config.suspend {
+            return Err(anyhow!("background and suspend cannot be used together"))
+        }
+
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+        for pattern in config.patterns.iter() {
+            if pattern.contains('/') {
+                // "*" がフォルダの区切りを越えないようにする
+                let glob = GlobBuilder::new(pattern.trim_start_matches('/')).literal_separator(true).build();
+                paths.add(glob.map_err(|e| anyhow!("invalid pattern {:?}: {}", pattern, e))?);
+            } else {
+                let glob = Glob::new(pattern);
+                names.add(glob.map_err(|e| anyhow!("invalid pattern {:?}: {}", pattern, e))?);
+            }
+        }
+
+        Ok(Action {
+            name: config.name.clone(),
+            key: config.key.clone(),
+            command: config.command.clone(),
+            background: config.background,
+            suspend: config.suspend,
+            refresh: config.refresh,
+            names: names.build()?,
+            paths: paths.build()?,
+            any: config.patterns.is_empty(),
+        })
+    }
+
+    // pathがこのアクションの対象か
+    pub fn matches(&self, path: &Path, root: &Path) -> bool {
+        if self.any {
+            return true
+        }
+        if let Some(name) = path.file_name() {
+            if self.names.is_match(name) {
+                return true
+            }
+        }
+        match path.strip_prefix(root) {
+            Ok(relative) => self.paths.is_match(relative),
+            Err(_) => false,
+        }
+    }
+}
+
+// 設定に誤りがあればエラー (名前の重複も含む)
+pub fn load_actions(configs: &[ActionConfig]) -> Result<Vec<Action>> {
+    let mut actions: Vec<Action> = Vec::new();
+    let mut names: HashSet<&str> = HashSet::new();
+    for config in configs.iter() {
+        if !names.insert(&config.name) {
+            return Err(anyhow!("actions: '{}' is defined twice", config.name));
+        }
+        let action = Action::new(config).map_err(|e| anyhow!("actions: {} (action '{}')", e, config.name))?;
+        actions.push(action);
+    }
+    Ok(actions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action(yaml: &str) -> Action {
+        let config: ActionConfig = serde_yaml::from_str(yaml).unwrap();
+        Action::new(&config).unwrap()
+    }
+
+    #[test]
+    fn name_patterns_match_in_any_folder() {
+        let a = action("{name: a, key: I, patterns: ['*.ipynb'], command: jupyter}");
+        let root = Path::new("/work");
+        assert!(a.matches(Path::new("/work/note.ipynb"), root));
+        assert!(a.matches(Path::new("/work/deep/dir/note.ipynb"), root));
+        assert!(!a.matches(Path::new("/work/note.py"), root));
+    }
+
+    #[test]
+    fn path_patterns_are_relative_to_the_root() {
+        let a = action("{name: a, key: G, patterns: ['proto/*.proto', '/gen/**/*.rs'], command: protoc}");
+        let root = Path::new("/work");
+        assert!(a.matches(Path::new("/work/proto/api.proto"), root));
+        // "*" はフォルダの区切りを越えない
+        assert!(!a.matches(Path::new("/work/proto/v1/api.proto"), root));
+        assert!(!a.matches(Path::new("/work/other/proto/api.proto"), root));
+        assert!(a.matches(Path::new("/work/gen/a/b/mod.rs"), root));
+        assert!(!a.matches(Path::new("/elsewhere/proto/api.proto"), root));
+    }
+
+    #[test]
+    fn no_patterns_match_everything() {
+        let a = action("{name: a, key: X, command: echo}");
+        assert!(a.matches(Path::new("/work/anything"), Path::new("/work")));
+    }
+
+    #[test]
+    fn invalid_configs_are_rejected() {
+        let configs: Vec<ActionConfig> = serde_yaml::from_str(
+            "[{name: a, key: X, command: echo}, {name: a, key: Y, command: echo}]"
+        ).unwrap();
+        assert!(load_actions(&configs).is_err());
+        let config: ActionConfig = serde_yaml::from_str("{name: a, key: X, command: ' '}").unwrap();
+        assert!(Action::new(&config).is_err());
+        let config: ActionConfig = serde_yaml::from_str("{name: a, key: X, command: a, background: true, suspend: true}").unwrap();
+        assert!(Action::new(&config).is_err());
+    }
+}
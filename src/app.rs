@@ -1,71 +1,129 @@
 #![allow(unused_imports, dead_code)]
 use anyhow::Result;
 use log;
-use crossterm::{cursor, execute, terminal::{
-    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
-    }
-};
-use std::io::stdout;
+use crossterm::event;
 use std::path::PathBuf;
 use std::sync::{Mutex, Arc};
+use std::time::Duration;
+use crate::action::load_actions;
 use crate::command::{read_command, Command, CommandRunner};
 use crate::config::Config;
+use crate::keymap::KeyMap;
 use crate::node_map::NodeMap;
+use crate::opener::load_openers;
+use crate::session::Session;
+use crate::sync::Sync;
+use crate::theme::Theme;
+use crate::utils::terminal;
 use crate::viewer::{Viewer, ConsoleMessageStatus};
 
-
 // シングルスレッドなのでRc+RefCellでいいけど，いずれマルチスレッドに拡張したいのでArc+Mutexにしておく
 pub struct App {
     node_map: Arc<Mutex<NodeMap>>,
     viewer: Arc<Mutex<Viewer>>,
     command_runner: CommandRunner,
     config: Arc<Config>,
+    keymap: Arc<KeyMap>,
+    poll_update: bool, // 監視が使えないときはコマンドごとに更新する
 }
 
 impl App {
-    pub fn new(root: PathBuf, config: Config) -> App {
+    pub fn new(root: PathBuf, config: Config, sync_client: Sync) -> Result<App> {
+        // キー割り当てや色の設定に誤りがあれば画面を切り替える前にエラーにする
+        let actions = load_actions(&config.actions)?;
+        let openers = load_openers(&config)?;
+        let keymap = Arc::new(KeyMap::new(&config.keymap, &actions)?);
+        let theme = Theme::new(&config.theme)?;
         let config = Arc::new(config);
-        let node_map = Arc::new(Mutex::new(NodeMap::new(root, config.clone())));
-        let viewer = Arc::new(Mutex::new(Viewer::new(node_map.clone(), config.clone())));
-        let command_runner = CommandRunner::new(node_map.clone(), viewer.clone(), config.clone());
-        App {
+        let mut node_map = NodeMap::new(root.clone(), config.clone());
+
+        // 前回開いていたフォルダを開き直す
+        let session = Session::load(&root);
+        if let Some(ref session) = session {
+            if let Err(e) = node_map.restore_open_paths(&session.open_paths) {
+                log::warn!("Failed to restore session: {:?}", e);
+            }
+        }
+
+        // auto_updateのときはファイルシステムを監視して変更を反映する
+        let mut poll_update = false;
+        if config.auto_update {
+            if let Err(e) = node_map.enable_watcher() {
+                log::warn!("Failed to start watcher, fall back to polling: {:?}", e);
+                poll_update = true;
+            }
+        }
+
+        let node_map = Arc::new(Mutex::new(node_map));
+        let viewer = Arc::new(Mutex::new(Viewer::new(node_map.clone(), config.clone(), theme)));
+
+        // カーソルと表示位置を戻す
+        if let Some(session) = session {
+            let mut viewer = viewer.lock().unwrap();
+            viewer.sync()?;
+            viewer.set_scroll(session.scroll);
+            let cursor_id = session.cursor.and_then(|path| node_map.lock().unwrap().find_id(&path));
+            if let Some(id) = cursor_id {
+                viewer.set_cursor_id(&id);
+            }
+        }
+        let command_runner = CommandRunner::new(node_map.clone(), viewer.clone(), config.clone(), keymap.clone(), actions, openers, sync_client);
+        Ok(App {
             node_map: node_map,
             viewer: viewer,
             command_runner: command_runner,
             config: config,
-        }
+            keymap,
+            poll_update,
+        })
     }
 
     pub fn run(&mut self) -> Result<()> {
-        enable_raw_mode()?;
-        execute!(stdout(), EnterAlternateScreen, cursor::Hide)?;
+        terminal::enter(self.config.mouse)?;
 
         loop {
             
             let mut viewer = self.viewer.lock().unwrap();
             viewer.sync()?; // viewerとnode_mapの同期
             viewer.display()?; // 表示
+            std::mem::drop(viewer);
+
+            // 入力を待つ間にファイルシステムの変更があれば再描画
+            if !self.wait_for_input()? {
+                continue;
+            }
 
             // 標準入力からコマンドを取得 ------------------------
-            let command = match read_command() {
+            let command = match read_command(&self.keymap) {
                 Err(e) => {
+                    let mut viewer = self.viewer.lock().unwrap();
                     viewer.set_console_message(format!("{}", e), ConsoleMessageStatus::Error);
                     continue;
                 }
+                // コンソールメッセージを消さないようにそのまま待つ
+                Ok(Command::None) => continue,
                 Ok(command) => {
-                    if command == Command::Quit{ break }
+                    if command == Command::Quit{
+                        // 実行中のジョブがあるときは確認してから終了
+                        match self.command_runner.confirm_quit() {
+                            Ok(_) => break,
+                            Err(e) => {
+                                let mut viewer = self.viewer.lock().unwrap();
+                                viewer.set_console_message(format!("{}", e), ConsoleMessageStatus::Error);
+                                continue;
+                            }
+                        }
+                    }
+                    let mut viewer = self.viewer.lock().unwrap();
                     viewer.clear_console_message();
                     command
                 }
             };
             log::info!("Command accepted: {:?}", command);
 
-            // command runner内でviewerをborrowするのでこのスコープ内ではドロップ
-            std::mem::drop(viewer);
-
             // コマンドの実行
             let result = self.command_runner.run_command(command);
-            if self.config.auto_update {
+            if self.poll_update {
                 self.command_runner.run_command(Command::Update)?;
             }
 
@@ -76,10 +134,49 @@ impl App {
             }
         }
 
-        execute!(stdout(), cursor::Show, LeaveAlternateScreen)?;
-        disable_raw_mode()?;
+        if let Err(e) = self.save_session() {
+            log::warn!("Failed to save session: {:?}", e);
+        }
+
+        terminal::leave(self.config.mouse)?;
         
         Ok(())
     }
-}
 
+    // 開いているフォルダとカーソルの位置を保存 (ルートが変わっていれば変更後のルートで保存)
+    fn save_session(&self) -> Result<()> {
+        let node_map = self.node_map.lock().unwrap();
+        let viewer = self.viewer.lock().unwrap();
+        let session = Session {
+            open_paths: node_map.get_open_paths()?,
+            cursor: node_map.get_path(&viewer.get_cursor_id()).ok(),
+            scroll: viewer.get_scroll(),
+        };
+        session.save(&node_map.get_path(&node_map.get_root_id())?)
+    }
+
+    // ----------------------------------------------------------------
+    // 入力があればtrue，先にツリーやジョブの状態が変わったらfalseを返す
+    // ----------------------------------------------------------------
+    fn wait_for_input(&mut self) -> Result<bool> {
+        loop {
+            if event::poll(Duration::from_millis(100))? {
+                return Ok(true);
+            }
+            // ジョブの進捗とファイルの変更は毎回両方とも確認する
+            let jobs_changed = self.command_runner.poll_jobs()?;
+            let files_changed = self.node_map.lock().unwrap().handle_fs_events()?;
+            // ファイルが変わったらgitの状態も別のスレッドで取り直し，終わったら描き直す
+            let git_changed = {
+                let mut viewer = self.viewer.lock().unwrap();
+                if files_changed {
+                    viewer.start_git_refresh();
+                }
+                viewer.poll_git_status()
+            };
+            if jobs_changed || files_changed || git_changed {
+                return Ok(false);
+            }
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::utils::path::get_application_root;
+
+// ----------------------------------------------------------------
+// よく使うファイル/フォルダ (~/.sidebar/bookmarks.yaml に保存)
+// ----------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Bookmarks {
+    paths: Vec<PathBuf>,
+    #[serde(skip)]
+    file: Option<PathBuf>,
+}
+
+impl Bookmarks {
+    // 読み込めないときは空で始める
+    pub fn load() -> Bookmarks {
+        let file = match get_application_root() {
+            Ok(root) => root.join("bookmarks.yaml"),
+            Err(e) => {
+                log::warn!("Bookmarks are not persisted: {:?}", e);
+                return Bookmarks::default();
+            }
+        };
+
+        let mut bookmarks = match fs::read_to_string(&file) {
+            Ok(yaml) => serde_yaml::from_str(&yaml).unwrap_or_else(|e| {
+                log::warn!("Failed to parse bookmarks: {:?}", e);
+                Bookmarks::default()
+            }),
+            Err(_) => Bookmarks::default(),
+        };
+        bookmarks.file = Some(file);
+        bookmarks
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(ref file) = self.file {
+            fs::write(file, serde_yaml::to_string(self)?)?;
+        }
+        Ok(())
+    }
+
+    pub fn list(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    // 登録されていなければ追加，されていれば削除 (追加したときはtrue)
+    pub fn toggle(&mut self, path: &Path) -> Result<bool> {
+        let added = match self.paths.iter().position(|p| p == path) {
+            Some(i) => {
+                self.paths.remove(i);
+                false
+            }
+            None => {
+                self.paths.push(path.to_path_buf());
+                true
+            }
+        };
+        self.save()?;
+        Ok(added)
+    }
+
+    pub fn remove(&mut self, index: usize) -> Result<()> {
+        if index < self.paths.len() {
+            self.paths.remove(index);
+        }
+        self.save()
+    }
+}
@@ -2,18 +2,32 @@
 
 use anyhow::{anyhow, Result};
 use duct::cmd;
-use crossterm::event::{read, Event, KeyCode, KeyEvent, KeyEventKind};
+use crossterm::event::{poll, read, Event, KeyCode, KeyEvent, KeyEventKind, MouseButton, MouseEventKind};
 use log;
 use std::sync::{Mutex, Arc};
+use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use fs_extra;
 
+use crate::action::Action;
+use crate::bookmark::Bookmarks;
 use crate::node_map::NodeMap;
 use crate::viewer::{Viewer, ConsoleMessageStatus};
-use crate::config::Config;
-
-#[derive(Debug, PartialEq, Eq)]
+use crate::config::{Config, DeleteMode, SortConfig};
+use crate::job::{Job, JobKind, JobState};
+use crate::journal::{remove_existing, Journal, Operation};
+use crate::keymap::KeyMap;
+use crate::opener::{self, Opener};
+use crate::search;
+use crate::shell;
+use crate::sync::Sync;
+use crate::trash::{Trash, TrashItem};
+use crate::utils::file::{remove_path, create_symlink};
+use crate::utils::terminal;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Command {
     Enter,
     OpenFolder,
@@ -34,9 +48,49 @@ pub enum Command {
     Down,
     JumpUp,
     JumpDown,
+    ToggleMark,
+    MarkRange,
+    MarkAll,
+    ClearMarks,
+    ShowTrash,
+    Undo,
+    Redo,
+    ShowJobs,
+    Search,
+    DeepSearch,
+    SearchNext,
+    SearchPrev,
+    TogglePreview,
+    ToggleHidden,
+    CycleSort,
+    ReverseSort,
+    ToggleFoldersFirst,
+    ToggleBookmark,
+    ShowBookmarks,
+    SetRoot,
+    ParentRoot,
+    RootBack,
+    RootForward,
+    Click { row: u16, column: u16 },
+    ScrollUp,
+    ScrollDown,
+    Run,
+    ShowOutput,
+    Action(usize), // config.yamlのactionsの番号
+    OpenWith,
+    None, // 何もしない (マウスのボタンを離したときなど)
 }
 
-pub fn read_command() -> Result<Command> {
+// 深い検索で一度に開く一致の数
+const MAX_REVEAL: usize = 30;
+// ホイール1回でスクロールする行数
+const SCROLL_LINES: isize = 3;
+// 出力パネルで左右キー/ホイールを使ったときに動かす行数
+const OUTPUT_PAGE_LINES: usize = 10;
+// この時間内に同じ行を2回クリックしたらダブルクリック
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+pub fn read_command(keymap: &KeyMap) -> Result<Command> {
     // キーが押されたときと話されたときでイベントが送信されるので，押されたときのみ受理
     loop {
         let event = read()?;
@@ -46,60 +100,68 @@ pub fn read_command() -> Result<Command> {
                 if e.kind == KeyEventKind::Release {
                     continue;
                 }
-                key_to_command(e)
+                key_to_command(keymap, e)
             }
             Event::Resize(_, _) => Ok(Command::Resize),
+            Event::Mouse(e) => match e.kind {
+                MouseEventKind::Down(MouseButton::Left) => Ok(Command::Click { row: e.row, column: e.column }),
+                MouseEventKind::ScrollUp => Ok(Command::ScrollUp),
+                MouseEventKind::ScrollDown => Ok(Command::ScrollDown),
+                _ => Ok(Command::None),
+            },
             _ => Err(anyhow!("Unacceptable Key")),
         };
         return command;
     }
 }
 
-fn key_to_command(key_event: KeyEvent) -> Result<Command> {
-    match key_event.code {
-        KeyCode::Char('p') => Ok(Command::ShowPath),
-        KeyCode::Char('u') => Ok(Command::Update),
-        KeyCode::Char('s') => Ok(Command::Shell),
-        KeyCode::Char('n') => Ok(Command::NewFile),
-        KeyCode::Char('N') => Ok(Command::NewFolder),
-        KeyCode::Char('r') => Ok(Command::Rename),
-        KeyCode::Char('m') => Ok(Command::Move),
-        KeyCode::Char('c') => Ok(Command::Copy),
-        KeyCode::Char('y') => Ok(Command::Sync),
-        KeyCode::Char('l') => Ok(Command::Link),
-        KeyCode::Enter => Ok(Command::Enter),
-        KeyCode::Tab => Ok(Command::OpenFolder),
-        KeyCode::Backspace => Ok(Command::Delete),
-        KeyCode::Esc => Ok(Command::Quit),
-        KeyCode::Left => Ok(Command::JumpUp),
-        KeyCode::Right => Ok(Command::JumpDown),
-        KeyCode::Down => Ok(Command::Down),
-        KeyCode::Up => Ok(Command::Up),
-        KeyCode::Char(c) => Err(anyhow!("Invalid Command: {}", c)),
-        _ => Err(anyhow!("Invalid Command: {:?}", key_event.code)),
+fn key_to_command(keymap: &KeyMap, key_event: KeyEvent) -> Result<Command> {
+    match keymap.get(&key_event) {
+        Some(command) => Ok(command),
+        None => match key_event.code {
+            KeyCode::Char(c) => Err(anyhow!("Invalid Command: {}", c)),
+            _ => Err(anyhow!("Invalid Command: {:?}", key_event.code)),
+        }
     }
 }
 
 // =====================================================================================
-// struct Job {
-//     command: Command,
-//     job_id: u16,
-//     node: String
-// }
 pub struct CommandRunner {
     node_map: Arc<Mutex<NodeMap>>,
     viewer: Arc<Mutex<Viewer>>,
     config: Arc<Config>,
-    // jobs: Vec<Job>
+    keymap: Arc<KeyMap>,
+    actions: Vec<Action>,
+    openers: Vec<Opener>, // ファイルを開くプログラム (優先するものが先)
+    sync_client: Sync,
+    journal: Journal,
+    jobs: Vec<Job>,
+    next_job_id: u16,
+    bookmarks: Bookmarks,
+    root_back: Vec<PathBuf>,    // 前のルート (新しいものが後ろ)
+    root_forward: Vec<PathBuf>, // 戻る前のルート
+    last_click: Option<(Instant, usize)>, // ダブルクリックの判定用 (時刻と行)
+    last_output: Option<(String, shell::Output)>, // 最後に実行したコマンドとその出力
 }
 
 impl CommandRunner{
-    pub fn new(node_map: Arc<Mutex<NodeMap>>, viewer: Arc<Mutex<Viewer>>, config: Arc<Config>) -> CommandRunner{
+    pub fn new(node_map: Arc<Mutex<NodeMap>>, viewer: Arc<Mutex<Viewer>>, config: Arc<Config>, keymap: Arc<KeyMap>, actions: Vec<Action>, openers: Vec<Opener>, sync_client: Sync) -> CommandRunner{
         CommandRunner{
             node_map: node_map,
             viewer: viewer,
             config: config,
-            // jobs: Vec::new()
+            keymap,
+            actions,
+            openers,
+            sync_client,
+            journal: Journal::load(),
+            jobs: Vec::new(),
+            next_job_id: 1,
+            bookmarks: Bookmarks::load(),
+            root_back: Vec::new(),
+            root_forward: Vec::new(),
+            last_click: None,
+            last_output: None,
         }
     }
 
@@ -118,12 +180,43 @@ impl CommandRunner{
             Command::Link => {self.link()?;},
             Command::Quit => {},
             Command::Update => {self.update()?;},
-            Command::Shell => {},
+            Command::Shell => {self.shell()?;},
             Command::Resize => {self.resize()?;},
             Command::Up => {self.up()?;},
             Command::Down => {self.down()?},
             Command::JumpUp => {self.jump_up()?;},
             Command::JumpDown => {self.jump_down()?;},
+            Command::ToggleMark => {self.toggle_mark()?;},
+            Command::MarkRange => {self.mark_range()?;},
+            Command::MarkAll => {self.mark_all()?;},
+            Command::ClearMarks => {self.clear_marks()?;},
+            Command::ShowTrash => {self.show_trash()?;},
+            Command::Undo => {self.undo()?;},
+            Command::Redo => {self.redo()?;},
+            Command::ShowJobs => {self.show_jobs()?;},
+            Command::Search => {self.search(false)?;},
+            Command::DeepSearch => {self.search(true)?;},
+            Command::SearchNext => {self.search_next(true)?;},
+            Command::SearchPrev => {self.search_next(false)?;},
+            Command::TogglePreview => {self.toggle_preview()?;},
+            Command::ToggleHidden => {self.toggle_hidden()?;},
+            Command::CycleSort => {self.change_sort(|sort| sort.mode = sort.mode.next())?;},
+            Command::ReverseSort => {self.change_sort(|sort| sort.reverse = !sort.reverse)?;},
+            Command::ToggleFoldersFirst => {self.change_sort(|sort| sort.folders_first = !sort.folders_first)?;},
+            Command::ToggleBookmark => {self.toggle_bookmark()?;},
+            Command::ShowBookmarks => {self.show_bookmarks()?;},
+            Command::SetRoot => {self.set_root()?;},
+            Command::ParentRoot => {self.parent_root()?;},
+            Command::RootBack => {self.move_root_history(true)?;},
+            Command::RootForward => {self.move_root_history(false)?;},
+            Command::Click { row, column } => {self.click(row, column)?;},
+            Command::ScrollUp => {self.scroll(-SCROLL_LINES)?;},
+            Command::ScrollDown => {self.scroll(SCROLL_LINES)?;},
+            Command::Run => {self.run()?;},
+            Command::ShowOutput => {self.show_output()?;},
+            Command::Action(index) => {self.run_action(index)?;},
+            Command::OpenWith => {self.open_with()?;},
+            Command::None => {},
         }
         return Ok(())
 
@@ -139,7 +232,7 @@ impl CommandRunner{
             viewer.display()?;
             std::mem::drop(viewer);
 
-            match read_command() {
+            match read_command(&self.keymap) {
                 Ok(Command::Up) => {self.up()?},
                 Ok(Command::Down) => {self.down()?},
                 Ok(Command::JumpUp) => {self.jump_up()?},
@@ -215,7 +308,7 @@ impl CommandRunner{
         );
         viewer.display()?;
         
-        match read_command() {
+        match read_command(&self.keymap) {
             Ok(Command::Enter) => return Ok(()),
             _ => return Err(anyhow!("Cancelled!"))
         }
@@ -240,6 +333,87 @@ impl CommandRunner{
         return Ok(path)
     }
 
+    // 操作対象のパス (マークがあればマークしたノード，なければカーソル上のノード)
+    // マークしたフォルダの中にあるノードはフォルダごと扱われるので除く
+    fn get_target_paths(&self) -> Result<Vec<PathBuf>> {
+        let viewer = self.viewer.lock().unwrap();
+        let node_map = self.node_map.lock().unwrap();
+
+        let marked_ids = viewer.get_marked_ids();
+        if marked_ids.is_empty() {
+            return Ok(vec![node_map.get_path(&viewer.get_cursor_id())?])
+        }
+
+        let paths = marked_ids.iter().map(|id| node_map.get_path(id)).collect::<Result<Vec<PathBuf>>>()?;
+        let filtered = paths.iter().filter(|path| {
+            !paths.iter().any(|other| other != *path && path.starts_with(other))
+        }).cloned().collect();
+        Ok(filtered)
+    }
+
+    // メッセージ用 (1つならパス，複数なら個数)
+    fn describe_paths(paths: &[PathBuf]) -> String {
+        match paths {
+            [path] => path.to_string_lossy().into_owned(),
+            _ => format!("{} items", paths.len()),
+        }
+    }
+
+    // 移動元とフォルダ名から(移動元, 移動先)の組を作る
+    fn make_destination_pairs(&self, from_paths: &[PathBuf], to_dir: &Path) -> Result<Vec<(PathBuf, PathBuf)>> {
+        let mut pairs: Vec<(PathBuf, PathBuf)> = Vec::new();
+        for from_path in from_paths.iter() {
+            // フォルダを自分の中にコピー/移動しようとしたとき
+            if from_path.is_dir() && to_dir.starts_with(from_path) {
+                return Err(anyhow!("Cannot put {:?} into itself", from_path))
+            }
+            let file_name = match from_path.file_name() {
+                Some(name) => name,
+                None => return Err(anyhow!("Invalid path"))
+            };
+            // 同じ場所へのコピー/移動 (上書きすると元が消える)
+            let to_path = to_dir.join(file_name);
+            if &to_path == from_path {
+                return Err(anyhow!("{:?} is already there", from_path))
+            }
+            pairs.push((from_path.clone(), to_path));
+        }
+        Ok(pairs)
+    }
+
+    // 移動先が既に存在するものがあればまとめて1回だけ確認
+    fn confirm_overwrite_all(&mut self, pairs: &[(PathBuf, PathBuf)]) -> Result<()> {
+        let existing: Vec<&PathBuf> = pairs.iter().map(|(_, to)| to).filter(|to| to.exists()).collect();
+        match existing.len() {
+            0 => Ok(()),
+            1 => self.confirm_overwrite(&existing[0].clone()),
+            n => {
+                if self.config.skip_exist {
+                    return Err(anyhow!("{} items already exist!", n))
+                }
+                self.confirm(format!("Overwrite {} items?", n))
+            }
+        }
+    }
+
+    // まとめて実行したあとのメッセージ表示とマークの解除
+    fn finish_bulk(&mut self, summary: String, errors: Vec<String>) -> Result<()> {
+        let mut viewer = self.viewer.lock().unwrap();
+        viewer.clear_marks();
+        if errors.is_empty() {
+            viewer.set_console_message(summary, ConsoleMessageStatus::Notify);
+            return Ok(())
+        }
+        for e in errors.iter() {
+            log::error!("{}", e);
+        }
+        viewer.set_console_message(
+            format!("{} ({} failed: {})", summary, errors.len(), errors.join(", ")),
+            ConsoleMessageStatus::Error
+        );
+        Ok(())
+    }
+
     // ↓ コマンドたち ↓ ======================================================================
 
     // エディタでファイルを開く -------------------------
@@ -248,10 +422,75 @@ impl CommandRunner{
         if path.is_file() == false {
             return Err(anyhow!("Not file"))
         }
-        cmd!(&self.config.editor_command, path).stderr_capture().run()?;
+        // 一致するものが無くてもxdg-openなどが残る
+        let opener = match opener::candidates(&self.openers, &path).first() {
+            Some(opener) => (*opener).clone(),
+            None => return Err(anyhow!("No opener")),
+        };
+        self.open_by(&opener, &path)
+    }
+
+    // vimなど端末で動くものは画面を端末に戻して終わるまで待ち，ツリーを読み直す
+    fn open_by(&mut self, opener: &Opener, path: &Path) -> Result<()> {
+        if !opener.terminal {
+            return opener.open(path)
+        }
+        let result = self.run_in_terminal(opener.expression(path), None);
+        self.update()?;
+        let output = result.map_err(|e| anyhow!("{}: {}", opener.name, e))?;
+        if !output.status.success() {
+            return Err(anyhow!("{}: {}", opener.name, output.status))
+        }
         Ok(())
     }
 
+    // 開くプログラムを選んでファイルを開く ------------------
+    fn open_with(&mut self) -> Result<()> {
+        let path = self.get_cursor_path()?;
+        if !path.is_file() {
+            return Err(anyhow!("Not file"))
+        }
+        let openers: Vec<Opener> = opener::candidates(&self.openers, &path).into_iter().cloned().collect();
+        let title = String::from("Open with  Enter: open / Esc: close");
+        let lines = openers.iter().map(|opener| {
+            let terminal = if opener.terminal { "  [terminal]" } else { "" };
+            if opener.name == opener.command {
+                format!("{}{}", opener.name, terminal)
+            } else {
+                format!("{}  ({}){}", opener.name, opener.command, terminal)
+            }
+        }).collect();
+
+        let mut viewer = self.viewer.lock().unwrap();
+        viewer.open_panel(title, lines);
+        std::mem::drop(viewer);
+
+        let chosen = loop {
+            let mut viewer = self.viewer.lock().unwrap();
+            viewer.display()?;
+            let selected = viewer.get_panel_cursor();
+            std::mem::drop(viewer);
+
+            match read_command(&self.keymap) {
+                Ok(Command::Up) => {self.viewer.lock().unwrap().panel_cursor_up();},
+                Ok(Command::Down) => {self.viewer.lock().unwrap().panel_cursor_down();},
+                Ok(Command::Resize) => {self.resize()?;},
+                Ok(Command::Enter) if selected.is_some() => break selected,
+                Ok(Command::Quit) => break None,
+                _ => {}
+            }
+        };
+
+        // 端末を渡すことがあるので閉じてから開く
+        let mut viewer = self.viewer.lock().unwrap();
+        viewer.close_panel();
+        std::mem::drop(viewer);
+        match chosen {
+            Some(i) => self.open_by(&openers[i], &path),
+            None => Ok(()),
+        }
+    }
+
     // フォルダを展開 ------------------------------
     fn open_folder(&mut self) -> Result<()> {
         let viewer = self.viewer.lock().unwrap();
@@ -282,9 +521,12 @@ impl CommandRunner{
 
         // 上書きしますか
         self.confirm_overwrite(&new_file_path)?;
+        let mut operations: Vec<Operation> = remove_existing(&new_file_path, self.config.delete_mode)?.into_iter().collect();
         
         fs::File::create(&new_file_path)?;
         log::info!("New file created: {:?}", &new_file_path);
+        operations.push(Operation::NewFile { path: new_file_path.clone() });
+        self.journal.record(operations)?;
 
         let mut viewer = self.viewer.lock().unwrap();
         viewer.set_console_message(
@@ -310,9 +552,12 @@ impl CommandRunner{
 
         // 上書きしますか
         self.confirm_overwrite(&new_dir_path)?;
+        let mut operations: Vec<Operation> = remove_existing(&new_dir_path, self.config.delete_mode)?.into_iter().collect();
         
         fs::create_dir(&new_dir_path)?;
         log::info!("New folder created: {:?}", &new_dir_path);
+        operations.push(Operation::NewFolder { path: new_dir_path.clone() });
+        self.journal.record(operations)?;
 
         let mut viewer = self.viewer.lock().unwrap();
         viewer.set_console_message(
@@ -322,65 +567,153 @@ impl CommandRunner{
         Ok(())
     }
 
-    // コピー ------------------------------------
+    // コピー (バックグラウンドで実行) ------------------------------------
     fn copy(&mut self) -> Result<()> {
-        let from_path = self.get_cursor_path()?;
+        let from_paths = self.get_target_paths()?;
 
         // 移動先を入力する
-        let to_path_dir = self.select_directory_by_secondoly_cursor(format!("Copy from: {}", from_path.to_string_lossy()))?;
-        let file_name = from_path.file_name().unwrap();
-        let to_path = to_path_dir.join(file_name);
-
+        let to_path_dir = self.select_directory_by_secondoly_cursor(
+            format!("Copy from: {}", Self::describe_paths(&from_paths))
+        )?;
+        let pairs = self.make_destination_pairs(&from_paths, &to_path_dir)?;
 
         // 移動先のパスが既に存在しているとき
-        self.confirm_overwrite(&to_path)?;
-
-        // 実行 (上書きするかは確認しているので上書きオプションはtrue)
-        if from_path.is_dir() {
-            let mut option = fs_extra::dir::CopyOptions::new();
-            option.overwrite = true;
-            option.copy_inside = true;
-            fs_extra::dir::copy(&from_path, &to_path, &option)?;
-        } else {
-            let mut option = fs_extra::file::CopyOptions::new();
-            option.overwrite = true;
-            fs_extra::file::copy(&from_path, &to_path, &option)?;
-        }
+        self.confirm_overwrite_all(&pairs)?;
 
-        log::info!("Copy from: {:?}, to: {:?}", &from_path, &to_path);
-
-        let mut viewer = self.viewer.lock().unwrap();
-        viewer.set_console_message(format!("Copy to: {}", to_path.to_string_lossy()), ConsoleMessageStatus::Notify);
-        Ok(())
+        // 実行 (上書きするかは確認しているので上書きする)
+        self.start_job(JobKind::Copy, pairs, &to_path_dir)
     }
 
-    // ファイルの移動 ------------------------------
+    // ファイルの移動 (バックグラウンドで実行) ------------------------------
     fn move_(&mut self) -> Result<()> {
-        let from_path = self.get_cursor_path()?;
+        let from_paths = self.get_target_paths()?;
 
         // 移動先を入力する
-        let to_path_dir = self.select_directory_by_secondoly_cursor(format!("Move from: {}", from_path.to_string_lossy()))?;
-        let file_name = from_path.file_name().unwrap();
-        let to_path = to_path_dir.join(file_name);
+        let to_path_dir = self.select_directory_by_secondoly_cursor(
+            format!("Move from: {}", Self::describe_paths(&from_paths))
+        )?;
+        let pairs = self.make_destination_pairs(&from_paths, &to_path_dir)?;
 
         // 移動先のパスが既に存在しているかどうか
-        self.confirm_overwrite(&to_path)?;
+        self.confirm_overwrite_all(&pairs)?;
 
         // 実行 (上書きするかは確認しているので強制的に上書き)
-        if from_path.is_dir() {
-            let mut option = fs_extra::dir::CopyOptions::new();
-            option.overwrite = true;
-            option.copy_inside = true;
-            fs_extra::dir::move_dir(&from_path, &to_path, &option)?;
-        } else {
-            let mut option = fs_extra::file::CopyOptions::new();
-            option.overwrite = true;
-            fs_extra::file::move_file(&from_path, &to_path, &option)?;
+        self.start_job(JobKind::Move, pairs, &to_path_dir)
+    }
+
+    fn start_job(&mut self, kind: JobKind, pairs: Vec<(PathBuf, PathBuf)>, to_dir: &Path) -> Result<()> {
+        let from_paths: Vec<PathBuf> = pairs.iter().map(|(from, _)| from.clone()).collect();
+        let node = format!("{} -> {}", Self::describe_paths(&from_paths), to_dir.to_string_lossy());
+        let job = Job::spawn(self.next_job_id, kind, node, pairs, self.config.delete_mode);
+        self.next_job_id = self.next_job_id.wrapping_add(1);
+        log::info!("Job started: {}", job.status());
+
+        let mut viewer = self.viewer.lock().unwrap();
+        viewer.clear_marks();
+        viewer.set_console_message(format!("Job [{}] started", job.job_id), ConsoleMessageStatus::Info);
+        self.jobs.push(job);
+        Ok(())
+    }
+
+    // ----------------------------------------------------------------
+    // ジョブの状態を確認してステータス行を更新する
+    // ジョブが終わったか，進捗の表示が変わったときはtrue (再描画が必要)
+    // ----------------------------------------------------------------
+    pub fn poll_jobs(&mut self) -> Result<bool> {
+        let mut changed = false;
+        let mut messages: Vec<(String, ConsoleMessageStatus)> = Vec::new();
+        for job in self.jobs.iter_mut() {
+            let result = match job.take_result() {
+                Some(result) => result,
+                None => continue,
+            };
+            changed = true;
+            log::info!("Job finished: {}", job.status());
+            for e in result.errors.iter() {
+                log::error!("{}", e);
+            }
+            self.journal.record(result.operations)?;
+            messages.push(match result.state {
+                JobState::Cancelled => (format!("Job [{}] cancelled", job.job_id), ConsoleMessageStatus::Info),
+                JobState::Failed => (
+                    format!("Job [{}] failed: {}", job.job_id, result.errors.join(", ")),
+                    ConsoleMessageStatus::Error
+                ),
+                _ => (format!("Job [{}] done: {:?} {}", job.job_id, job.kind, job.node), ConsoleMessageStatus::Notify),
+            });
+        }
+
+        let running: Vec<String> = self.jobs.iter().filter(|job| job.is_running()).map(|job| job.status()).collect();
+        let mut viewer = self.viewer.lock().unwrap();
+        for (message, status) in messages {
+            viewer.set_console_message(message, status);
+        }
+        let status_changed = viewer.set_status_line(match running.len() {
+            0 => None,
+            1 => Some(running[0].clone()),
+            n => Some(format!("{} (+{} jobs)", running[0], n - 1)),
+        });
+        Ok(changed || status_changed)
+    }
+
+    pub fn has_running_jobs(&self) -> bool {
+        self.jobs.iter().any(|job| job.is_running())
+    }
+
+    // 終了する前に実行中のジョブをキャンセルするか確認 ----------------------
+    pub fn confirm_quit(&mut self) -> Result<()> {
+        if !self.has_running_jobs() {
+            return Ok(())
+        }
+        self.confirm(String::from("Jobs are running. Cancel them and quit?"))?;
+        for job in self.jobs.iter() {
+            job.cancel();
+        }
+        // コピー中のファイルは次の区切りで止まるので，作りかけのものを消し終わるまで待って記録する
+        for job in self.jobs.iter_mut() {
+            job.join();
+        }
+        self.poll_jobs()?;
+        Ok(())
+    }
+
+    // ジョブの一覧 (Backspaceで実行中のものはキャンセル，終わったものは一覧から消す) ---
+    fn show_jobs(&mut self) -> Result<()> {
+        let title = String::from("Jobs  Backspace: cancel / remove / Esc: close");
+        loop {
+            self.poll_jobs()?;
+            let lines = self.jobs.iter().map(|job| job.status()).collect();
+
+            let mut viewer = self.viewer.lock().unwrap();
+            viewer.open_panel(title.clone(), lines);
+            viewer.display()?;
+            let selected = viewer.get_panel_cursor();
+            std::mem::drop(viewer);
+
+            // 進捗を更新するために入力を待ちすぎない
+            if !poll(Duration::from_millis(200))? {
+                continue;
+            }
+            match read_command(&self.keymap) {
+                Ok(Command::Up) => {self.viewer.lock().unwrap().panel_cursor_up();},
+                Ok(Command::Down) => {self.viewer.lock().unwrap().panel_cursor_down();},
+                Ok(Command::Resize) => {self.resize()?;},
+                Ok(Command::Delete) => {
+                    if let Some(i) = selected {
+                        if self.jobs[i].is_running() {
+                            self.jobs[i].cancel();
+                        } else {
+                            self.jobs.remove(i);
+                        }
+                    }
+                },
+                Ok(Command::Quit) => break,
+                _ => {}
+            }
         }
-        log::info!("Move from: {:?}, to: {:?}", &from_path, &to_path);
 
         let mut viewer = self.viewer.lock().unwrap();
-        viewer.set_console_message(format!("Move to: {}", to_path.to_string_lossy()), ConsoleMessageStatus::Notify);
+        viewer.close_panel();
         Ok(())
     }
 
@@ -394,10 +727,16 @@ impl CommandRunner{
             None => return Err(anyhow!("Invalid path"))
         };
 
+        if new_path == from_path {
+            return Err(anyhow!("Same name"))
+        }
         self.confirm_overwrite(&new_path)?;
+        let mut operations: Vec<Operation> = remove_existing(&new_path, self.config.delete_mode)?.into_iter().collect();
 
         fs::rename(&from_path, &new_path)?;
         log::info!("Rename from: {:?}, to: {:?}", &from_path, &new_path);
+        operations.push(Operation::Rename { from: from_path.clone(), to: new_path.clone() });
+        self.journal.record(operations)?;
 
         let mut viewer = self.viewer.lock().unwrap();
         viewer.set_console_message(
@@ -407,27 +746,105 @@ impl CommandRunner{
         Ok(())
     }
 
-    // 削除 --------------------------------------------------------
+    // 削除 (設定によってゴミ箱へ移動) ---------------------------------------
     fn delete(&mut self) -> Result<()> {
-        let path = self.get_cursor_path()?;
-        self.confirm(format!("Remove {}?", path.to_string_lossy()))?;
+        let paths = self.get_target_paths()?;
+        let use_trash = self.config.delete_mode == DeleteMode::Trash;
+        if use_trash {
+            self.confirm(format!("Move {} to trash?", Self::describe_paths(&paths)))?;
+        } else {
+            self.confirm(format!("Remove {}?", Self::describe_paths(&paths)))?;
+        }
 
         // 削除
-        if path.is_dir() {
-            fs::remove_dir_all(&path)?;
+        // 完全に削除したものは元に戻せないので履歴には残さない
+        let mut errors: Vec<String> = Vec::new();
+        let mut operations: Vec<Operation> = Vec::new();
+        for path in paths.iter() {
+            let result = if use_trash {
+                Trash::for_path(path).and_then(|trash| trash.put(path)).map(|item| {
+                    operations.push(Operation::Trash { path: path.clone(), name: item.name });
+                })
+            } else {
+                remove_path(path)
+            };
+            match result {
+                Ok(_) => log::info!("Delete {:?}", path),
+                Err(e) => errors.push(format!("{}: {}", path.to_string_lossy(), e)),
+            }
+        }
+        self.journal.record(operations)?;
+
+        let verb = if use_trash { "Trashed" } else { "Removed" };
+        let summary = if paths.len() == 1 {
+            format!("{}: {}", verb, paths[0].to_string_lossy())
         } else {
-            fs::remove_file(&path)?;
+            format!("{} {} items", verb, paths.len() - errors.len())
+        };
+        self.finish_bulk(summary, errors)
+    }
+
+    // ゴミ箱の中身を表示して元に戻す/完全に削除する ----------------------------
+    fn show_trash(&mut self) -> Result<()> {
+        // ホームのゴミ箱と，ルートが別のファイルシステムならそのゴミ箱
+        let root = {
+            let node_map = self.node_map.lock().unwrap();
+            node_map.get_path(&node_map.get_root_id())?
+        };
+        let trashes = Trash::for_listing(&root)?;
+        let title = String::from("Trash  Enter: restore / Backspace: purge / Esc: close");
+
+        loop {
+            let mut items: Vec<(&Trash, TrashItem)> = Vec::new();
+            for trash in trashes.iter() {
+                items.extend(trash.list()?.into_iter().map(|item| (trash, item)));
+            }
+            items.sort_by(|(_, a), (_, b)| b.deletion_date.cmp(&a.deletion_date));
+            let lines = items.iter().map(|(_, item)| {
+                format!("{}  {}", item.deletion_date.replace('T', " "), item.original_path.to_string_lossy())
+            }).collect();
+
+            let mut viewer = self.viewer.lock().unwrap();
+            viewer.open_panel(title.clone(), lines);
+            viewer.display()?;
+            let selected = viewer.get_panel_cursor().map(|i| items[i].clone());
+            std::mem::drop(viewer);
+
+            let result = match read_command(&self.keymap) {
+                Ok(Command::Up) => {self.viewer.lock().unwrap().panel_cursor_up(); Ok(())},
+                Ok(Command::Down) => {self.viewer.lock().unwrap().panel_cursor_down(); Ok(())},
+                Ok(Command::Resize) => self.resize(),
+                Ok(Command::Enter) => match selected {
+                    Some((trash, item)) => self.restore_from_trash(trash, &item),
+                    None => Ok(()),
+                },
+                Ok(Command::Delete) => match selected {
+                    Some((trash, item)) => self.confirm(format!("Delete {} permanently?", item.original_path.to_string_lossy()))
+                        .and_then(|_| trash.purge(&item)),
+                    None => Ok(()),
+                },
+                Ok(Command::Quit) => break,
+                _ => Ok(()),
+            };
+
+            let mut viewer = self.viewer.lock().unwrap();
+            match result {
+                Ok(_) => viewer.clear_console_message(),
+                Err(e) => viewer.set_console_message(format!("{}", e), ConsoleMessageStatus::Error),
+            }
         }
-        log::info!("Delete {:?}", &path);
 
         let mut viewer = self.viewer.lock().unwrap();
-        viewer.set_console_message(
-            format!("Removed: {}", path.to_string_lossy()), 
-            ConsoleMessageStatus::Notify
-        );
+        viewer.close_panel();
+        viewer.clear_console_message();
         Ok(())
     }
 
+    fn restore_from_trash(&mut self, trash: &Trash, item: &TrashItem) -> Result<()> {
+        self.confirm_overwrite(&item.original_path)?;
+        trash.restore(item)
+    }
+
     // パスを表示 ----------------------------------------------------
     fn show_path(&mut self) -> Result<()> {
         let node_map = self.node_map.lock().unwrap();
@@ -441,52 +858,659 @@ impl CommandRunner{
 
     // 別のシェルのカレントディレクトリを同期 -------------------------------------
     fn sync(&mut self) -> Result<()> {
-        // let node_map = self.node_map.lock().unwrap();
-        // let mut viewer = self.viewer.lock().unwrap();
-        // let id = viewer.get_cursor_id();
-        // let path = node_map.get_path(&id)?;
-        // // shell_command::export(path_string, self.cfg.export_variable_name);
-        // viewer.set_console_message(format!("{}", path.to_string_lossy()), ConsoleMessageStatus::Notify);
-        // Ok(())
+        if !self.config.sync_server {
+            return Err(anyhow!("Sync is disabled (sync_server: false)"))
+        }
+
+        // ファイルの場合は親ディレクトリを送る
+        let path = self.get_cursor_path()?;
+        let dir = if path.is_dir() {
+            path
+        } else {
+            match path.parent() {
+                Some(p) => p.to_path_buf(),
+                None => return Err(anyhow!("Invalid path"))
+            }
+        };
+
+        self.sync_client.send(&dir)?;
+        log::info!("Sync: {:?}", &dir);
+
+        let mut viewer = self.viewer.lock().unwrap();
+        viewer.set_console_message(format!("Sync: {}", dir.to_string_lossy()), ConsoleMessageStatus::Notify);
         Ok(())
     }
 
     // シンボリックリンクを作成 ----------------------------------------
     fn link(&mut self) -> Result<()> {
-        #[cfg(unix)] // Unix系システム（Linux、macOSなど）
-        fn create_symlink(source: &PathBuf, dest: &PathBuf) -> Result<()> {
-            use std::os::unix::fs::symlink;
-            symlink(source, dest)?;
-            Ok(())
+        let sources = self.get_target_paths()?;
+
+        // 移動先を入力する
+        let dest_dir = self.select_directory_by_secondoly_cursor(
+            format!("Link {}", Self::describe_paths(&sources))
+        )?;
+        let pairs = self.make_destination_pairs(&sources, &dest_dir)?;
+
+        // 移動先のパスが既に存在しているかどうか
+        self.confirm_overwrite_all(&pairs)?;
+
+        let mut errors: Vec<String> = Vec::new();
+        let mut operations: Vec<Operation> = Vec::new();
+        for (source, dest) in pairs.iter() {
+            // 上書きするものは先に片付ける (ゴミ箱へ移したものは取り消せる)
+            match remove_existing(dest, self.config.delete_mode) {
+                Ok(trashed) => operations.extend(trashed),
+                Err(e) => {
+                    errors.push(format!("{}: {}", dest.to_string_lossy(), e));
+                    continue;
+                }
+            }
+            match create_symlink(source, dest) {
+                Ok(_) => {
+                    log::info!("Link from: {:?}, to: {:?}", source, dest);
+                    operations.push(Operation::Link { source: source.clone(), dest: dest.clone() });
+                }
+                Err(e) => errors.push(format!("{}: {}", source.to_string_lossy(), e)),
+            }
         }
+        self.journal.record(operations)?;
+
+        let summary = if pairs.len() == 1 {
+            format!("Linked: {}", pairs[0].1.to_string_lossy())
+        } else {
+            format!("Linked {} items in: {}", pairs.len() - errors.len(), dest_dir.to_string_lossy())
+        };
+        self.finish_bulk(summary, errors)
+    }
+
+    // 最後の操作を取り消す --------------------------------------------
+    fn undo(&mut self) -> Result<()> {
+        let description = self.journal.undo()?;
+        let mut viewer = self.viewer.lock().unwrap();
+        viewer.set_console_message(format!("Undo: {}", description), ConsoleMessageStatus::Notify);
+        Ok(())
+    }
+
+    // 取り消した操作をやり直す ------------------------------------------
+    fn redo(&mut self) -> Result<()> {
+        let description = self.journal.redo()?;
+        let mut viewer = self.viewer.lock().unwrap();
+        viewer.set_console_message(format!("Redo: {}", description), ConsoleMessageStatus::Notify);
+        Ok(())
+    }
 
-        #[cfg(windows)] // Windowsシステム用
-        fn create_symlink(source: &PathBuf, dest: &PathBuf) -> Result<()> {
-            use std::os::windows::fs::symlink_file;
-            symlink_file(source, dest)?;
-            Ok(())
+    // 検索 (入力するたびに一致する行へカーソルを移動) ------------------------
+    // deepのときは開いていないフォルダの中も探し，確定したら一致したものの親フォルダを開く
+    fn search(&mut self, deep: bool) -> Result<()> {
+        let (root, paths, limited) = {
+            let node_map = self.node_map.lock().unwrap();
+            let root = node_map.get_path(&node_map.get_root_id())?;
+            let (paths, limited) = if deep {
+                search::walk(&root, |path, is_dir| node_map.is_ignored(path, is_dir))
+            } else {
+                (Vec::new(), false)
+            };
+            (root, paths, limited)
+        };
+        let label = if deep { "Deep search" } else { "Search" };
+        let start_idx = self.viewer.lock().unwrap().get_cursor_idx();
+
+        let mut query = String::new();
+        loop {
+            let mut viewer = self.viewer.lock().unwrap();
+            viewer.set_search(Some(query.clone()))?;
+            viewer.set_cursor_idx(start_idx);
+            viewer.jump_to_hit(true, true);
+            let count = if deep {
+                let found = Self::deep_matches(&query, &paths, &root).len();
+                format!("{}{} found", found, if limited { "+" } else { "" })
+            } else {
+                Self::search_count(&viewer)
+            };
+            let count = if query.is_empty() { String::new() } else { format!("  [{}]", count) };
+            viewer.set_console_message(format!("{}: {}{}", label, query, count), ConsoleMessageStatus::Info);
+            viewer.display()?;
+            std::mem::drop(viewer);
+
+            match read()? {
+                Event::Key(k) => {
+                    if k.kind == KeyEventKind::Release {
+                        continue
+                    }
+                    match k.code {
+                        KeyCode::Char(c) => {query.push(c);},
+                        KeyCode::Backspace => {query.pop();},
+                        KeyCode::Esc => {
+                            let mut viewer = self.viewer.lock().unwrap();
+                            viewer.set_search(None)?;
+                            viewer.set_cursor_idx(start_idx);
+                            return Err(anyhow!("Search aborted!"))
+                        }
+                        KeyCode::Enter => break,
+                        _ => {}
+                    }
+                }
+                Event::Resize(_, _) => {self.resize()?;}
+                _ => {}
+            }
         }
 
-        let source = self.get_cursor_path()?;
+        // 空のまま確定したときは検索を終了
+        if query.is_empty() {
+            let mut viewer = self.viewer.lock().unwrap();
+            viewer.set_search(None)?;
+            viewer.clear_console_message();
+            return Ok(())
+        }
 
-        // 移動先を入力する
-        let dest_dir = self.select_directory_by_secondoly_cursor(format!("Link {}", source.to_string_lossy()))?;
-        let file_name = source.file_name().unwrap();
-        let dest = dest_dir.join(file_name);
+        // 一致したもの (スコア順) の親フォルダを開いて一番良いものへ移動
+        let mut best_id = None;
+        if deep {
+            let matches = Self::deep_matches(&query, &paths, &root);
+            let mut node_map = self.node_map.lock().unwrap();
+            for path in matches.iter().take(MAX_REVEAL) {
+                let id = node_map.reveal(path)?;
+                if best_id.is_none() {
+                    best_id = id;
+                }
+            }
+        }
 
-        // 移動先のパスが既に存在しているかどうか
-        self.confirm_overwrite(&dest)?;
+        let mut viewer = self.viewer.lock().unwrap();
+        viewer.sync()?;
+        match best_id {
+            Some(id) => viewer.set_cursor_id(&id),
+            None => {viewer.jump_to_hit(true, true);}
+        }
+        let message = format!("{}: {}  [{}]", label, query, Self::search_count(&viewer));
+        viewer.set_console_message(message, ConsoleMessageStatus::Notify);
+        Ok(())
+    }
+
+    // 次(前)の一致へ --------------------------------------------------
+    fn search_next(&mut self, forward: bool) -> Result<()> {
+        let mut viewer = self.viewer.lock().unwrap();
+        if viewer.get_search_count().is_none() {
+            return Err(anyhow!("No search"))
+        }
+        if !viewer.jump_to_hit(forward, false) {
+            return Err(anyhow!("No match"))
+        }
+        let message = format!("Search  [{}]", Self::search_count(&viewer));
+        viewer.set_console_message(message, ConsoleMessageStatus::Info);
+        Ok(())
+    }
+
+    fn search_count(viewer: &Viewer) -> String {
+        match viewer.get_search_count() {
+            Some((_, 0)) => String::from("no match"),
+            Some((current, total)) => format!("{}/{}", current, total),
+            None => String::new(),
+        }
+    }
+
+    // 深い検索で一致したパスをスコアの高い順に
+    fn deep_matches(query: &str, paths: &[PathBuf], root: &PathBuf) -> Vec<PathBuf> {
+        if query.is_empty() {
+            return Vec::new()
+        }
+        let mut matches: Vec<(i64, &PathBuf)> = paths.iter().filter_map(|path| {
+            let name = path.file_name()?.to_string_lossy().into_owned();
+            let relative = path.strip_prefix(root).ok()?;
+            let (text, _) = search::target_text(query, &name, relative);
+            search::fuzzy_match(query, &text).map(|m| (m.score, path))
+        }).collect();
+        matches.sort_by_key(|m| std::cmp::Reverse(m.0));
+        matches.into_iter().map(|(_, path)| path.clone()).collect()
+    }
+
+    // プレビューの表示/非表示 ----------------------------------------
+    fn toggle_preview(&mut self) -> Result<()> {
+        let mut viewer = self.viewer.lock().unwrap();
+        viewer.toggle_preview();
+        Ok(())
+    }
+
+    // 無視しているものの表示/非表示 -----------------------------------
+    fn toggle_hidden(&mut self) -> Result<()> {
+        let mut node_map = self.node_map.lock().unwrap();
+        let show_hidden = node_map.toggle_hidden();
+        std::mem::drop(node_map);
+
+        let mut viewer = self.viewer.lock().unwrap();
+        let message = if show_hidden { "Show hidden files" } else { "Hide hidden files" };
+        viewer.set_console_message(String::from(message), ConsoleMessageStatus::Info);
+        Ok(())
+    }
+
+    // 並べ替えの変更 ------------------------------------------------
+    fn change_sort<F: FnOnce(&mut SortConfig)>(&mut self, change: F) -> Result<()> {
+        let mut node_map = self.node_map.lock().unwrap();
+        let mut sort = node_map.get_sort();
+        change(&mut sort);
+        node_map.set_sort(sort);
+        std::mem::drop(node_map);
+
+        let mut viewer = self.viewer.lock().unwrap();
+        let message = format!(
+            "Sort: {:?}{}{}",
+            sort.mode,
+            if sort.reverse { " (desc)" } else { "" },
+            if sort.folders_first { ", folders first" } else { "" }
+        );
+        viewer.set_console_message(message, ConsoleMessageStatus::Info);
+        Ok(())
+    }
+
+    // カーソル上のノードをブックマークに追加/削除 ----------------------
+    fn toggle_bookmark(&mut self) -> Result<()> {
+        let path = self.get_cursor_path()?;
+        let added = self.bookmarks.toggle(&path)?;
+        let verb = if added { "Bookmarked" } else { "Removed bookmark" };
+        let mut viewer = self.viewer.lock().unwrap();
+        viewer.set_console_message(format!("{}: {}", verb, path.to_string_lossy()), ConsoleMessageStatus::Info);
+        Ok(())
+    }
+
+    // ブックマークを表示して移動/削除する -------------------------------
+    fn show_bookmarks(&mut self) -> Result<()> {
+        let title = String::from("Bookmarks  Enter: jump / Backspace: remove / Esc: close");
+
+        loop {
+            let paths = self.bookmarks.list().to_vec();
+            let lines = paths.iter().map(|path| {
+                let missing = if path.exists() { "" } else { "  (missing)" };
+                format!("{}{}", path.to_string_lossy(), missing)
+            }).collect();
+
+            let mut viewer = self.viewer.lock().unwrap();
+            viewer.open_panel(title.clone(), lines);
+            viewer.display()?;
+            let selected = viewer.get_panel_cursor();
+            std::mem::drop(viewer);
+
+            let result = match read_command(&self.keymap) {
+                Ok(Command::Up) => {self.viewer.lock().unwrap().panel_cursor_up(); Ok(())},
+                Ok(Command::Down) => {self.viewer.lock().unwrap().panel_cursor_down(); Ok(())},
+                Ok(Command::Resize) => self.resize(),
+                Ok(Command::Enter) => match selected {
+                    Some(i) => match self.jump_to_path(&paths[i]) {
+                        Ok(_) => break,
+                        Err(e) => Err(e),
+                    },
+                    None => Ok(()),
+                },
+                Ok(Command::Delete) => match selected {
+                    Some(i) => self.bookmarks.remove(i),
+                    None => Ok(()),
+                },
+                Ok(Command::Quit) => break,
+                _ => Ok(()),
+            };
+
+            let mut viewer = self.viewer.lock().unwrap();
+            match result {
+                Ok(_) => viewer.clear_console_message(),
+                Err(e) => viewer.set_console_message(format!("{}", e), ConsoleMessageStatus::Error),
+            }
+        }
+
+        let mut viewer = self.viewer.lock().unwrap();
+        viewer.close_panel();
+        Ok(())
+    }
+
+    // パスまでフォルダを開いてカーソルを置く
+    // ルートの外にあるときはそのフォルダ (ファイルなら親フォルダ) をルートにする
+    fn jump_to_path(&mut self, path: &Path) -> Result<()> {
+        if !path.exists() {
+            return Err(anyhow!("{} does not exist", path.to_string_lossy()))
+        }
+        if !path.starts_with(self.get_root_path()?) {
+            self.change_root(Self::folder_of(path)?)?;
+        }
+
+        let id = self.node_map.lock().unwrap().reveal(path)?;
+        let mut viewer = self.viewer.lock().unwrap();
+        viewer.sync()?;
+        if let Some(id) = id {
+            viewer.set_cursor_id(&id);
+        }
+        viewer.set_console_message(path.to_string_lossy().into_owned(), ConsoleMessageStatus::Info);
+        Ok(())
+    }
+
+    // カーソル上のフォルダ (ファイルなら親フォルダ) をルートにする -------------
+    fn set_root(&mut self) -> Result<()> {
+        let path = self.get_cursor_path()?;
+        self.change_root(Self::folder_of(&path)?)
+    }
+
+    // ルートの親フォルダをルートにする ----------------------------------
+    fn parent_root(&mut self) -> Result<()> {
+        let root = self.get_root_path()?;
+        match root.parent() {
+            Some(parent) => self.change_root(parent.to_path_buf()),
+            None => Err(anyhow!("Already at the top")),
+        }
+    }
+
+    // ルートの履歴を戻る/進む --------------------------------------------
+    fn move_root_history(&mut self, back: bool) -> Result<()> {
+        let root = if back { self.root_back.pop() } else { self.root_forward.pop() };
+        let root = match root {
+            Some(root) => root,
+            None => return Err(anyhow!("No more history")),
+        };
+        let current = self.get_root_path()?;
+        // 移動できなかったもの (削除されたフォルダなど) は履歴から消える
+        self.apply_root(root)?;
+        if back {
+            self.root_forward.push(current);
+        } else {
+            self.root_back.push(current);
+        }
+        Ok(())
+    }
+
+    // ルートを変えて履歴に残す
+    fn change_root(&mut self, root: PathBuf) -> Result<()> {
+        let current = self.get_root_path()?;
+        if root == current {
+            return Ok(())
+        }
+        self.apply_root(root)?;
+        self.root_back.push(current);
+        self.root_forward.clear();
+        Ok(())
+    }
+
+    // ルートを変えて表示を合わせる (カーソルがあったノードが残っていればそこに置く)
+    fn apply_root(&mut self, root: PathBuf) -> Result<()> {
+        let cursor_id = self.viewer.lock().unwrap().get_cursor_id();
+        let mut node_map = self.node_map.lock().unwrap();
+        node_map.change_root(root.clone())?;
+        let keep_cursor = node_map.contains(&cursor_id);
+        std::mem::drop(node_map);
+
+        let mut viewer = self.viewer.lock().unwrap();
+        viewer.reset_for_new_root()?;
+        if keep_cursor {
+            viewer.set_cursor_id(&cursor_id);
+        }
+        viewer.set_console_message(format!("Root: {}", root.to_string_lossy()), ConsoleMessageStatus::Info);
+        Ok(())
+    }
 
-        create_symlink(&source, &dest)?;
+    fn get_root_path(&self) -> Result<PathBuf> {
+        let node_map = self.node_map.lock().unwrap();
+        node_map.get_path(&node_map.get_root_id())
+    }
+
+    // ファイルなら親フォルダ
+    fn folder_of(path: &Path) -> Result<PathBuf> {
+        if path.is_dir() {
+            return Ok(path.to_path_buf())
+        }
+        match path.parent() {
+            Some(p) => Ok(p.to_path_buf()),
+            None => Err(anyhow!("Invalid path")),
+        }
+    }
+
+    // マウスのクリック ------------------------------------------------
+    // 行をクリックしたらカーソルを移動し，フォルダのアイコンなら開閉する
+    // 同じ行をダブルクリックしたらファイルは開くプログラムで開き，フォルダは開閉する (アイコンの上では1回だけ)
+    fn click(&mut self, row: u16, column: u16) -> Result<()> {
+        let mut viewer = self.viewer.lock().unwrap();
+        let (idx, on_icon) = match viewer.get_node_at(row as usize, column as usize) {
+            Some(hit) => hit,
+            None => return Ok(()),
+        };
+        viewer.set_cursor_idx(idx);
+        std::mem::drop(viewer);
+
+        let now = Instant::now();
+        let is_double = match self.last_click {
+            Some((time, last_idx)) => last_idx == idx && now.duration_since(time) < DOUBLE_CLICK,
+            None => false,
+        };
+        // 3回目のクリックは新しい1回目として扱う
+        self.last_click = if is_double { None } else { Some((now, idx)) };
+
+        let is_dir = self.get_cursor_path()?.is_dir();
+        if on_icon && is_dir {
+            // 1回目のクリックで開閉しているので，ダブルクリックの2回目で戻さない
+            if is_double {
+                return Ok(())
+            }
+            return self.open_folder()
+        }
+        if !is_double {
+            return Ok(())
+        }
+        if is_dir {
+            self.open_folder()
+        } else {
+            self.open_file()
+        }
+    }
+
+    // ホイールでのスクロール -------------------------------------------
+    fn scroll(&mut self, lines: isize) -> Result<()> {
+        let mut viewer = self.viewer.lock().unwrap();
+        viewer.scroll(lines);
+        Ok(())
+    }
+
+    // マークの切り替え ----------------------------------------------
+    fn toggle_mark(&mut self) -> Result<()> {
         let mut viewer = self.viewer.lock().unwrap();
-        viewer.set_console_message(format!("Linked: {}", dest.to_string_lossy()), ConsoleMessageStatus::Notify);
+        viewer.toggle_mark();
+        Ok(())
+    }
+
+    // 範囲をマーク --------------------------------------------------
+    fn mark_range(&mut self) -> Result<()> {
+        let mut viewer = self.viewer.lock().unwrap();
+        viewer.mark_range();
+        Ok(())
+    }
+
+    // 同じフォルダのノードを全てマーク -------------------------------
+    fn mark_all(&mut self) -> Result<()> {
+        let mut viewer = self.viewer.lock().unwrap();
+        viewer.mark_siblings()
+    }
+
+    // マークを全て外す ----------------------------------------------
+    fn clear_marks(&mut self) -> Result<()> {
+        let mut viewer = self.viewer.lock().unwrap();
+        viewer.clear_marks();
         Ok(())
     }
 
     // ツリーを更新 -------------------------------------------------
     fn update(&mut self) -> Result<()> {
         let mut node_map = self.node_map.lock().unwrap();
-        node_map.update()
+        node_map.update()?;
+        std::mem::drop(node_map);
+
+        let mut viewer = self.viewer.lock().unwrap();
+        viewer.start_git_refresh();
+        Ok(())
+    }
+
+    // 入力したコマンドを選択しているノードに対して実行する -------------------
+    // {path} {dir} {name} {stem} {paths} を置き換え，カーソルのフォルダで実行する
+    fn run(&mut self) -> Result<()> {
+        let template = self.input(String::from("Run ({path} {dir} {name} {stem} {paths})"))?;
+        if template.trim().is_empty() {
+            return Err(anyhow!("No command"))
+        }
+        let path = self.get_cursor_path()?;
+        let dir = Self::folder_of(&path)?;
+        let targets = self.get_target_paths()?;
+        let command_line = shell::expand(&template, &path, &dir, &targets);
+        self.run_shell(command_line, &dir, true)
+    }
+
+    // 終わるまで待ち，出力をパネルに表示する
+    fn run_shell(&mut self, command_line: String, dir: &Path, refresh: bool) -> Result<()> {
+        // 終わるまで待つので実行中であることを先に表示しておく
+        let mut viewer = self.viewer.lock().unwrap();
+        viewer.set_console_message(format!("Running: {}", command_line), ConsoleMessageStatus::Info);
+        viewer.display()?;
+        std::mem::drop(viewer);
+
+        let output = shell::run(&command_line, dir)?;
+        self.last_output = Some((command_line, output));
+        if refresh {
+            self.update()?;
+        }
+        self.show_output()
+    }
+
+    // config.yamlのactionsで定義したコマンド ----------------------------------
+    // マークしたノード (無ければカーソル上のノード) のうちパターンに一致するものを対象にする
+    fn run_action(&mut self, index: usize) -> Result<()> {
+        let action = match self.actions.get(index) {
+            Some(action) => action.clone(),
+            None => return Err(anyhow!("Unknown action")),
+        };
+        let root = self.get_root_path()?;
+        let cursor = self.get_cursor_path()?;
+        let targets: Vec<PathBuf> = self.get_target_paths()?.into_iter()
+            .filter(|path| action.matches(path, &root))
+            .collect();
+
+        // カーソル上のノードが対象外なら対象の最初のノードを {path} にする
+        let path = if action.matches(&cursor, &root) {
+            cursor
+        } else {
+            match targets.first() {
+                Some(path) => path.clone(),
+                None => {
+                    let name = cursor.file_name().unwrap_or_default().to_string_lossy().into_owned();
+                    return Err(anyhow!("{}: not applicable to {}", action.name, name))
+                }
+            }
+        };
+        let dir = Self::folder_of(&path)?;
+        let command_line = shell::expand(&action.command, &path, &dir, &targets);
+        log::info!("Action {}: {}", action.name, command_line);
+
+        if action.background {
+            // 終了を待つスレッドで結果をログに残す (変更は監視で反映される)
+            // 終了時に待たないようにランタイムの外のスレッドにする
+            let handle = cmd!("sh", "-c", &command_line).dir(&dir)
+                .stdin_null().stdout_null().stderr_null()
+                .unchecked().start()?;
+            let name = action.name.clone();
+            std::thread::spawn(move || {
+                match handle.wait() {
+                    Ok(output) => log::info!("Action {} finished: {}", name, output.status),
+                    Err(e) => log::warn!("Action {} failed: {:?}", name, e),
+                }
+            });
+            let mut viewer = self.viewer.lock().unwrap();
+            viewer.set_console_message(format!("Started: {}", action.name), ConsoleMessageStatus::Info);
+        } else if action.suspend {
+            let result = self.run_in_terminal(cmd!("sh", "-c", &command_line).dir(&dir), None);
+            if action.refresh {
+                self.update()?;
+            }
+            let output = result?;
+            let (message, status) = match output.status.code() {
+                Some(0) => (format!("Done: {}", action.name), ConsoleMessageStatus::Notify),
+                Some(code) => (format!("Exit {}: {}", code, action.name), ConsoleMessageStatus::Error),
+                None => (format!("Killed by signal: {}", action.name), ConsoleMessageStatus::Error),
+            };
+            let mut viewer = self.viewer.lock().unwrap();
+            viewer.set_console_message(message, status);
+        } else {
+            self.run_shell(command_line, &dir, action.refresh)?;
+        }
+        Ok(())
+    }
+
+    // 最後に実行したコマンドの出力 (上下キーでスクロール，Esc/Enterで閉じる) ---
+    fn show_output(&mut self) -> Result<()> {
+        let (command_line, output) = match self.last_output {
+            Some(ref last) => last.clone(),
+            None => return Err(anyhow!("No output")),
+        };
+        let title = format!("$ {}  Esc: close", command_line);
+        let status = if output.success() {ConsoleMessageStatus::Notify} else {ConsoleMessageStatus::Error};
+        let message = match output.code {
+            Some(code) => format!("Exit {}", code),
+            None => String::from("Killed by signal"),
+        };
+
+        let mut viewer = self.viewer.lock().unwrap();
+        viewer.open_panel(title.clone(), output.lines.clone());
+        viewer.set_console_message(message.clone(), status.clone());
+        std::mem::drop(viewer);
+
+        loop {
+            self.viewer.lock().unwrap().display()?;
+            match read_command(&self.keymap) {
+                Ok(Command::Up) => {self.viewer.lock().unwrap().panel_cursor_up();},
+                Ok(Command::Down) => {self.viewer.lock().unwrap().panel_cursor_down();},
+                Ok(Command::JumpUp) | Ok(Command::ScrollUp) => {
+                    let mut viewer = self.viewer.lock().unwrap();
+                    for _ in 0..OUTPUT_PAGE_LINES {
+                        viewer.panel_cursor_up();
+                    }
+                },
+                Ok(Command::JumpDown) | Ok(Command::ScrollDown) => {
+                    let mut viewer = self.viewer.lock().unwrap();
+                    for _ in 0..OUTPUT_PAGE_LINES {
+                        viewer.panel_cursor_down();
+                    }
+                },
+                Ok(Command::Resize) => {self.resize()?;},
+                Ok(Command::Quit) | Ok(Command::Enter) => break,
+                _ => {}
+            }
+        }
+
+        // 閉じた後も終了コードは残しておく
+        let mut viewer = self.viewer.lock().unwrap();
+        viewer.close_panel();
+        viewer.set_console_message(format!("{}: {}", message, command_line), status);
+        Ok(())
+    }
+
+    // カーソルのフォルダ (ファイルなら親フォルダ) でシェルを開く -------------
+    // シェルを終了するまでTUIを止め，戻ったらツリーを読み直す
+    fn shell(&mut self) -> Result<()> {
+        let dir = Self::folder_of(&self.get_cursor_path()?)?;
+        let shell = env::var("SHELL").unwrap_or_else(|_| String::from("/bin/sh"));
+
+        let hint = format!("{} (exit to return to sidebar)", dir.to_string_lossy());
+        let result = self.run_in_terminal(cmd!(&shell).dir(&dir), Some(hint));
+
+        // シェルの中でファイルが変わっているかもしれない
+        self.update()?;
+        result.map_err(|e| anyhow!("Failed to start {}: {}", shell, e))?;
+        Ok(())
+    }
+
+    // 画面を端末に戻してexpressionを実行し，終わったら画面を描き直す ----------
+    // hintは実行前に端末に表示する
+    fn run_in_terminal(&mut self, expression: duct::Expression, hint: Option<String>) -> Result<std::process::Output> {
+        terminal::leave(self.config.mouse)?;
+        if let Some(hint) = hint {
+            println!("{}", hint);
+        }
+        // 中で開いたシェルがフックで受信側を起動しないように印をつける
+        let result = expression.env("SIDEBAR_SUBSHELL", "1").unchecked().run();
+        terminal::enter(self.config.mouse)?;
+
+        // 画面は上書きされ，端末の大きさも変わっているかもしれないので全て描き直す
+        self.resize()?;
+        Ok(result?)
     }
 
     // 画面のリサイズ -----------------------------------------------
@@ -520,4 +1544,4 @@ impl CommandRunner{
         let mut viewer = self.viewer.lock().unwrap();
         viewer.cursor_jump_down()
     }
-}
\ No newline at end of file
+}
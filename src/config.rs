@@ -3,9 +3,11 @@ use anyhow::Result;
 use dir;
 use serde::{Deserialize, Serialize};
 use serde_yaml;
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
 use std::io::{self, Write};
+use crate::keymap::default_keymap;
 use crate::utils::path::get_application_root;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -17,6 +19,152 @@ pub struct Config {
     pub skip_exist: bool,
     pub saving_memory: bool,
     pub auto_update: bool,
+    #[serde(default = "default_mouse")]
+    pub mouse: bool, // クリックとホイールを使う
+    #[serde(default)]
+    pub delete_mode: DeleteMode,
+    #[serde(default)]
+    pub keymap: BTreeMap<String, String>, // キー -> コマンド名 (既定の割り当てを上書き)
+    #[serde(default)]
+    pub preview: PreviewConfig,
+    #[serde(default)]
+    pub sort: SortConfig,
+    #[serde(default)]
+    pub theme: ThemeConfig,
+    #[serde(default)]
+    pub actions: Vec<ActionConfig>,
+    #[serde(default)]
+    pub openers: Vec<OpenerConfig>,
+}
+
+// ファイルを開くプログラム (上にあるものほど優先する)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OpenerConfig {
+    #[serde(default)]
+    pub name: String,          // 「開くプログラム」の一覧に出す名前 (省略するとcommand)
+    #[serde(default)]
+    pub patterns: Vec<String>, // ファイルの名前 ("*.md" など)
+    #[serde(default)]
+    pub mime: Vec<String>,     // 拡張子から決めたMIMEタイプ ("image/*" など)
+    pub command: String,       // {path} などが無ければ最後にパスを付け足す
+    #[serde(default)]
+    pub terminal: Option<bool>, // 画面を端末に戻して終わるまで待つ (省略するとvimなどは自動で判定)
+}
+
+// 独自のコマンド (キーを押すとパターンに一致するノードに対して実行する)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ActionConfig {
+    pub name: String,
+    pub key: String,
+    #[serde(default)]
+    pub patterns: Vec<String>, // 対象にするノードの名前/パス (空なら全て)
+    pub command: String,       // {path} {dir} {name} {stem} {paths} を置き換えて sh -c で実行
+    #[serde(default)]
+    pub background: bool,      // 終わるのを待たない
+    #[serde(default)]
+    pub suspend: bool,         // 画面を端末に戻して実行する
+    #[serde(default)]
+    pub refresh: bool,         // 終わった後にツリーを読み直す
+}
+
+// 並べ替えの基準
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortMode {
+    Name,      // 名前 (数字は数として比較，大文字小文字を区別しない)
+    Extension, // 拡張子 -> 名前
+    Size,
+    Modified,  // 更新日時
+    Created,   // 作成日時
+}
+
+impl SortMode {
+    // 切り替えキーで次に選ぶもの
+    pub fn next(&self) -> SortMode {
+        match self {
+            SortMode::Name => SortMode::Extension,
+            SortMode::Extension => SortMode::Size,
+            SortMode::Size => SortMode::Modified,
+            SortMode::Modified => SortMode::Created,
+            SortMode::Created => SortMode::Name,
+        }
+    }
+}
+
+// 並べ替えの設定 (実行中にキーで変更できる)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct SortConfig {
+    pub mode: SortMode,
+    pub reverse: bool,       // 降順
+    pub folders_first: bool, // フォルダをファイルより先に並べる
+}
+
+impl Default for SortConfig {
+    fn default() -> SortConfig {
+        SortConfig {
+            mode: SortMode::Extension,
+            reverse: false,
+            folders_first: true,
+        }
+    }
+}
+
+// プレビュー表示の設定
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PreviewConfig {
+    pub enabled: bool,     // 起動時に表示するか
+    pub width_ratio: f32,  // 画面の幅に対するプレビューの幅 (0.1~0.9)
+    pub max_bytes: u64,    // ファイルから読み込む最大のバイト数
+}
+
+impl Default for PreviewConfig {
+    fn default() -> PreviewConfig {
+        PreviewConfig {
+            enabled: false,
+            width_ratio: 0.5,
+            max_bytes: 64 * 1024,
+        }
+    }
+}
+
+// 色の設定 (presetの色をcolorsで上書きする)
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ThemeConfig {
+    pub preset: ThemePreset,
+    pub colors: BTreeMap<String, ColorSpec>, // "cursor", "icon.rust" など -> 色
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemePreset {
+    #[default]
+    Dark,
+    Light,
+}
+
+// 色の書き方: 名前 ("red", "bright_blue", "default")，256色の番号 (208)，"#rrggbb"
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum ColorSpec {
+    Index(u8),
+    Name(String),
+}
+
+// 削除したときにゴミ箱へ入れるか完全に削除するか
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DeleteMode {
+    #[default]
+    Trash,
+    Permanent,
+}
+
+// 古い設定ファイルにはmouseが無いので有効にしておく
+fn default_mouse() -> bool {
+    true
 }
 
 pub fn load_config() -> Result<Config> {
@@ -41,6 +189,14 @@ pub fn load_config() -> Result<Config> {
             skip_exist: true,
             saving_memory: true,
             auto_update: true,
+            mouse: true,
+            delete_mode: DeleteMode::Trash,
+            keymap: default_keymap(),
+            preview: PreviewConfig::default(),
+            sort: SortConfig::default(),
+            theme: ThemeConfig::default(),
+            actions: Vec::new(),
+            openers: Vec::new(),
         };
 
         // 保存
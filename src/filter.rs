@@ -0,0 +1,195 @@
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// フォルダごとに読む無視ファイル (後ろほど優先)
+pub const IGNORE_FILES: &[&str] = &[".gitignore", ".ignore", ".sidebarignore"];
+
+// ----------------------------------------------------------------
+// 表示しないファイルを決める
+// config.ignore (ルート基準) と各フォルダの .gitignore / .ignore / .sidebarignore を
+// gitignoreと同じ書式で読み，深いフォルダのものほど優先する
+// config.ignore はどの無視ファイルよりも優先する (無視ファイルの "!" で戻すことはできない)
+// ----------------------------------------------------------------
+#[derive(Debug)]
+pub struct IgnoreFilter {
+    root: PathBuf,
+    config_ignore: Gitignore,
+    parents: Vec<Gitignore>, // ルートより上のフォルダの無視ファイル (近いものが先)
+    cache: RefCell<HashMap<PathBuf, Gitignore>>, // ルート以下のフォルダの無視ファイル
+}
+
+impl IgnoreFilter {
+    pub fn new(root: &Path, patterns: &[String]) -> IgnoreFilter {
+        let mut builder = GitignoreBuilder::new(root);
+        for pattern in patterns.iter() {
+            if let Err(e) = builder.add_line(None, pattern) {
+                log::warn!("Invalid ignore pattern {:?}: {:?}", pattern, e);
+            }
+        }
+        let config_ignore = builder.build().unwrap_or_else(|_| Gitignore::empty());
+
+        // gitのリポジトリの中ならリポジトリのトップまでの無視ファイルと .git/info/exclude も読む
+        let mut parents: Vec<Gitignore> = Vec::new();
+        if let Some(toplevel) = root.ancestors().find(|dir| dir.join(".git").exists()) {
+            for dir in root.ancestors().skip(1) {
+                if !dir.starts_with(toplevel) {
+                    break;
+                }
+                if let Some(gitignore) = load_dir(dir) {
+                    parents.push(gitignore);
+                }
+            }
+            let (exclude, _) = Gitignore::new(toplevel.join(".git").join("info").join("exclude"));
+            parents.push(exclude);
+        }
+
+        IgnoreFilter {
+            root: root.to_path_buf(),
+            config_ignore,
+            parents,
+            cache: RefCell::new(HashMap::new()),
+        }
+    }
+
+    // 無視ファイルが変更されたときは読み直す
+    pub fn clear_cache(&self) {
+        self.cache.borrow_mut().clear();
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if path == self.root {
+            return false;
+        }
+
+        // config.ignore で決まればそれを使う ("!" で無視ファイルの指定を打ち消すこともできる)
+        match self.config_ignore.matched(path, is_dir) {
+            Match::Ignore(_) => return true,
+            Match::Whitelist(_) => return false,
+            Match::None => {}
+        }
+
+        // パスに近いフォルダの無視ファイルから順に見ていき，最初に決まったものを使う
+        let mut cache = self.cache.borrow_mut();
+        for dir in path.ancestors().skip(1) {
+            if !dir.starts_with(&self.root) {
+                break;
+            }
+            let gitignore = cache.entry(dir.to_path_buf()).or_insert_with(|| {
+                load_dir(dir).unwrap_or_else(Gitignore::empty)
+            });
+            match gitignore.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        for gitignore in self.parents.iter() {
+            match gitignore.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        false
+    }
+}
+
+// フォルダの無視ファイルをまとめて読む (1つも無ければNone)
+fn load_dir(dir: &Path) -> Option<Gitignore> {
+    let mut builder = GitignoreBuilder::new(dir);
+    let mut found = false;
+    for name in IGNORE_FILES.iter() {
+        let path = dir.join(name);
+        if path.is_file() {
+            if let Some(e) = builder.add(&path) {
+                log::warn!("Failed to read {:?}: {:?}", path, e);
+            }
+            found = true;
+        }
+    }
+    if !found {
+        return None;
+    }
+    match builder.build() {
+        Ok(gitignore) => Some(gitignore),
+        Err(e) => {
+            log::warn!("Invalid ignore file in {:?}: {:?}", dir, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // テストごとの一時フォルダに無視ファイルを書く
+    fn make_root(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("sidebar-filter-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&root);
+        for (path, content) in files.iter() {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        root
+    }
+
+    fn filter(root: &Path, patterns: &[&str]) -> IgnoreFilter {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        IgnoreFilter::new(root, &patterns)
+    }
+
+    #[test]
+    fn nested_ignore_files_take_priority() {
+        let root = make_root("nested", &[
+            (".gitignore", "*.log\nbuild/\n"),
+            ("sub/.gitignore", "!keep.log\n"),
+            ("sub/deep/.ignore", "keep.log\n"),
+        ]);
+        let filter = filter(&root, &[]);
+        assert!(filter.is_ignored(&root.join("a.log"), false));
+        assert!(filter.is_ignored(&root.join("build"), true));
+        assert!(!filter.is_ignored(&root.join("build"), false));
+        assert!(filter.is_ignored(&root.join("sub/other.log"), false));
+        assert!(!filter.is_ignored(&root.join("sub/keep.log"), false));
+        assert!(filter.is_ignored(&root.join("sub/deep/keep.log"), false));
+        assert!(!filter.is_ignored(&root, true));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn later_files_in_the_same_folder_take_priority() {
+        let root = make_root("same", &[
+            (".gitignore", "a.txt\n"),
+            (".sidebarignore", "!a.txt\nb.txt\n"),
+            (".ignore", "!b.txt\n"),
+        ]);
+        let filter = filter(&root, &[]);
+        assert!(!filter.is_ignored(&root.join("a.txt"), false));
+        assert!(filter.is_ignored(&root.join("b.txt"), false));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn config_ignore_takes_priority_over_ignore_files() {
+        let root = make_root("config", &[
+            (".gitignore", "!*.tmp\n*.log\n"),
+            ("sub/.gitignore", "!secret.txt\n"),
+        ]);
+        let filter = filter(&root, &["*.tmp", "/sub/secret.txt", "!debug.log"]);
+        // 無視ファイルの "!" では戻せない
+        assert!(filter.is_ignored(&root.join("a.tmp"), false));
+        assert!(filter.is_ignored(&root.join("sub/secret.txt"), false));
+        // config.ignore の "!" は無視ファイルの指定を打ち消す
+        assert!(!filter.is_ignored(&root.join("debug.log"), false));
+        assert!(filter.is_ignored(&root.join("other.log"), false));
+        // ルート基準なので別のフォルダの同じ名前は対象外
+        assert!(!filter.is_ignored(&root.join("secret.txt"), false));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,264 @@
+use anyhow::{anyhow, Result};
+use duct::cmd;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+// ファイルの状態 (後ろほど優先して親フォルダに伝える)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GitState {
+    Ignored,
+    Untracked,
+    Added,
+    Modified,
+    Conflicted,
+}
+
+impl GitState {
+    // 名前の後ろにつける印 (色はテーマで決める)
+    pub fn marker(&self) -> char {
+        match self {
+            GitState::Ignored => '!',
+            GitState::Untracked => '?',
+            GitState::Added => 'A',
+            GitState::Modified => 'M',
+            GitState::Conflicted => 'U',
+        }
+    }
+}
+
+// ノードにつける印
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GitMark {
+    File(GitState),  // そのファイル(フォルダ)自体の状態
+    Dirty(GitState), // 中に変更があるフォルダ
+}
+
+// ----------------------------------------------------------------
+// ワークツリーの状態 (git status --porcelain=v2 の結果)
+// ----------------------------------------------------------------
+#[derive(Debug)]
+pub struct GitStatus {
+    toplevel: PathBuf,
+    root: PathBuf,      // ツリーのルート (ノードのパスはこれを基準にする)
+    root_real: PathBuf, // シンボリックリンクを解決したルート
+    entries: HashMap<PathBuf, GitState>, // git statusに出てきたパス (フォルダのときは中身も同じ状態)
+    dirty: HashMap<PathBuf, GitState>,   // 変更を含むフォルダ
+    pending: Option<Receiver<Result<Vec<u8>>>>, // 別のスレッドで実行中のgit status
+    requested: bool, // 実行中にもう一度取り直すよう頼まれた
+}
+
+impl GitStatus {
+    // rootがgitのワークツリーの中になければNone
+    pub fn load(root: &Path) -> Option<GitStatus> {
+        let toplevel = cmd!("git", "-C", root, "rev-parse", "--show-toplevel")
+            .stdout_capture()
+            .stderr_null()
+            .read()
+            .ok()?;
+        let toplevel = PathBuf::from(toplevel.trim());
+        let root_real = fs::canonicalize(root).ok()?;
+
+        let mut status = GitStatus {
+            toplevel,
+            root: root.to_path_buf(),
+            root_real,
+            entries: HashMap::new(),
+            dirty: HashMap::new(),
+            pending: None,
+            requested: false,
+        };
+        // 大きなリポジトリでは時間がかかるので，状態は別のスレッドで取る
+        status.start_refresh();
+        Some(status)
+    }
+
+    // 別のスレッドでgit statusを実行する (結果はpoll_refreshで反映する)
+    // 画面の操作を止めないように，UIのスレッドではgit statusを待たない
+    // 実行中なら終わった後にもう一度実行する
+    pub fn start_refresh(&mut self) {
+        if self.pending.is_some() {
+            self.requested = true;
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        let toplevel = self.toplevel.clone();
+        thread::spawn(move || {
+            // 受け取る側が先に無くなっていても構わない
+            let _ = sender.send(Self::run_status(&toplevel));
+        });
+        self.pending = Some(receiver);
+        self.requested = false;
+    }
+
+    // 別のスレッドのgit statusが終わっていれば結果を反映してtrueを返す
+    pub fn poll_refresh(&mut self) -> bool {
+        let result = match self.pending {
+            Some(ref receiver) => match receiver.try_recv() {
+                Ok(result) => result,
+                Err(TryRecvError::Empty) => return false,
+                Err(TryRecvError::Disconnected) => Err(anyhow!("git status thread stopped")),
+            },
+            None => return false,
+        };
+        self.pending = None;
+        if self.requested {
+            self.start_refresh();
+        }
+        match result {
+            Ok(stdout) => {
+                self.apply(&stdout);
+                true
+            }
+            Err(e) => {
+                log::warn!("Failed to get git status: {:?}", e);
+                false
+            }
+        }
+    }
+
+    // git status の出力 (indexを書き換えないようにoptional locksを使わない)
+    fn run_status(toplevel: &Path) -> Result<Vec<u8>> {
+        let output = cmd!("git", "--no-optional-locks", "-C", toplevel, "status", "--porcelain=v2", "-z", "--ignored=matching")
+            .stdout_capture()
+            .stderr_null()
+            .unchecked()
+            .run()?;
+        if !output.status.success() {
+            return Err(anyhow!("git status failed in {:?}", toplevel));
+        }
+        Ok(output.stdout)
+    }
+
+    fn apply(&mut self, stdout: &[u8]) {
+        self.entries.clear();
+        self.dirty.clear();
+        let output = String::from_utf8_lossy(stdout).into_owned();
+        for (state, path) in Self::parse(&output) {
+            self.insert(path, state);
+        }
+    }
+
+    // --porcelain=v2 -z の出力を (状態, toplevelからのパス) にする
+    fn parse(output: &str) -> Vec<(GitState, &str)> {
+        let mut entries: Vec<(GitState, &str)> = Vec::new();
+        let mut records = output.split('\0');
+        while let Some(record) = records.next() {
+            let (state, path) = match record.chars().next() {
+                Some('1') => (Self::parse_xy(record), Self::nth_field(record, 8)),
+                Some('2') => {
+                    // 名前の変更は移動元のパスが次に続く
+                    records.next();
+                    (GitState::Added, Self::nth_field(record, 9))
+                }
+                Some('u') => (GitState::Conflicted, Self::nth_field(record, 10)),
+                Some('?') => (GitState::Untracked, record.get(2..)),
+                Some('!') => (GitState::Ignored, record.get(2..)),
+                _ => continue,
+            };
+            if let Some(path) = path {
+                entries.push((state, path));
+            }
+        }
+        entries
+    }
+
+    // "1 XY ..." のXY (index, worktree)
+    fn parse_xy(record: &str) -> GitState {
+        match record.get(2..4) {
+            Some(xy) if xy.starts_with('A') => GitState::Added,
+            _ => GitState::Modified,
+        }
+    }
+
+    // n個の空白区切りのフィールドの後ろ (パスには空白が含まれることがある)
+    fn nth_field(record: &str, n: usize) -> Option<&str> {
+        record.splitn(n + 1, ' ').nth(n)
+    }
+
+    // gitのパスをツリーのパスに直して登録 (ルートの外のものは使わない)
+    fn insert(&mut self, relative: &str, state: GitState) {
+        let real_path = self.toplevel.join(relative.trim_end_matches('/'));
+        let path = match real_path.strip_prefix(&self.root_real) {
+            Ok(r) => self.root.join(r),
+            // ルートを含むフォルダごと追跡されていない/無視されている場合
+            Err(_) if self.root_real.starts_with(&real_path) && state <= GitState::Untracked => self.root.clone(),
+            Err(_) => return,
+        };
+        // 無視されているものは親フォルダに伝えない
+        if state != GitState::Ignored {
+            for ancestor in path.ancestors().skip(1) {
+                if !ancestor.starts_with(&self.root) {
+                    break;
+                }
+                let current = self.dirty.entry(ancestor.to_path_buf()).or_insert(state);
+                if state > *current {
+                    *current = state;
+                }
+            }
+        }
+        self.entries.insert(path, state);
+    }
+
+    // パスにつける印
+    // 追跡されていないフォルダや無視されているフォルダの中はフォルダと同じ状態にする
+    pub fn get_mark(&self, path: &Path) -> Option<GitMark> {
+        if !path.starts_with(&self.root) {
+            return None;
+        }
+        if let Some(state) = self.entries.get(path) {
+            return Some(GitMark::File(*state));
+        }
+        for ancestor in path.ancestors().skip(1) {
+            if !ancestor.starts_with(&self.root) {
+                break;
+            }
+            match self.entries.get(ancestor) {
+                Some(state) if *state == GitState::Untracked || *state == GitState::Ignored => {
+                    return Some(GitMark::File(*state));
+                }
+                _ => {}
+            }
+        }
+        self.dirty.get(path).map(|state| GitMark::Dirty(*state))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_changed_and_added_entries() {
+        let output = "1 .M N... 100644 100644 100644 aaa bbb src/main.rs\0\
+                      1 A. N... 000000 100644 100644 000 ccc new file.txt\0";
+        let entries = GitStatus::parse(output);
+        assert_eq!(entries, vec![(GitState::Modified, "src/main.rs"), (GitState::Added, "new file.txt")]);
+    }
+
+    #[test]
+    fn parse_rename_skips_the_original_path() {
+        let output = "2 R. N... 100644 100644 100644 aaa bbb R100 renamed.rs\0old.rs\0? untracked.txt\0";
+        let entries = GitStatus::parse(output);
+        assert_eq!(entries, vec![(GitState::Added, "renamed.rs"), (GitState::Untracked, "untracked.txt")]);
+    }
+
+    #[test]
+    fn parse_conflicted_untracked_and_ignored_entries() {
+        let output = "u UU N... 100644 100644 100644 100644 aaa bbb ccc both.rs\0? dir/\0! target/\0";
+        let entries = GitStatus::parse(output);
+        assert_eq!(entries, vec![
+            (GitState::Conflicted, "both.rs"),
+            (GitState::Untracked, "dir/"),
+            (GitState::Ignored, "target/"),
+        ]);
+    }
+
+    #[test]
+    fn parse_ignores_unknown_and_empty_records() {
+        assert!(GitStatus::parse("").is_empty());
+        assert!(GitStatus::parse("# branch.oid abc\0\0").is_empty());
+    }
+}
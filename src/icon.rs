@@ -1,4 +1,5 @@
 use crate::color;
+use crate::theme::Theme;
 
 enum IconType {
     Python,
@@ -32,23 +33,66 @@ enum IconType {
     Other,
 }
 
-pub fn get_folder_icon(is_open: bool, nerd_font: bool) -> String {
+// テーマで色を決めるときの名前 (icon.<名前>)
+pub const ICON_NAMES: &[&str] = &[
+    "python", "rust", "go", "cpp", "c", "cs", "php", "ruby", "r", "java", "kotlin", "swift", "dart",
+    "javascript", "typescript", "react", "html", "css", "json", "yaml", "markdown", "toml", "git",
+    "docker", "text", "image", "sound", "pdf", "other",
+];
+
+impl IconType {
+    fn name(&self) -> &'static str {
+        match self {
+            IconType::Python => "python",
+            IconType::Rust => "rust",
+            IconType::Go => "go",
+            IconType::Cpp => "cpp",
+            IconType::C => "c",
+            IconType::Cs => "cs",
+            IconType::Php => "php",
+            IconType::Ruby => "ruby",
+            IconType::R => "r",
+            IconType::Java => "java",
+            IconType::Kotlin => "kotlin",
+            IconType::Swift => "swift",
+            IconType::Dart => "dart",
+            IconType::JavaScript => "javascript",
+            IconType::TypeScript => "typescript",
+            IconType::React => "react",
+            IconType::Html => "html",
+            IconType::Css => "css",
+            IconType::Json => "json",
+            IconType::Yaml => "yaml",
+            IconType::Markdown => "markdown",
+            IconType::Toml => "toml",
+            IconType::Git => "git",
+            IconType::Docker => "docker",
+            IconType::Text => "text",
+            IconType::Image => "image",
+            IconType::Sound => "sound",
+            IconType::Pdf => "pdf",
+            IconType::Other => "other",
+        }
+    }
+}
+
+pub fn get_folder_icon(is_open: bool, nerd_font: bool, theme: &Theme) -> String {
     if nerd_font {
         if is_open {
-            return format!("{}{}{}", color::front::YELLOW, "\u{f115}", color::RESET)
+            format!("{}{}{}", theme.folder(), "\u{f115}", color::RESET)
         }else {
-            return format!("{}{}{}", color::front::YELLOW, "\u{f114}", color::RESET)
+            format!("{}{}{}", theme.folder(), "\u{f114}", color::RESET)
         }
     }else {
         if is_open {
-            return String::from("📂")
+            String::from("📂")
         }else {
-            return String::from("📁")
+            String::from("📁")
         }
     }
 }
 
-pub fn get_file_icon(name: &String, nerd_font: bool) -> String {
+pub fn get_file_icon(name: &String, nerd_font: bool, theme: &Theme) -> String {
     let icon_type = get_file_icon_type(name);
 
     // nerd fontがインストールされてないとき
@@ -89,7 +133,7 @@ pub fn get_file_icon(name: &String, nerd_font: bool) -> String {
         IconType::Other => "\u{e64e}"
     };
 
-    return String::from(icon)
+    format!("{}{}{}", theme.icon(icon_type.name()), icon, color::RESET)
 }
 
 fn get_file_icon_type(name: &String) -> IconType {
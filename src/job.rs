@@ -0,0 +1,296 @@
+use anyhow::Result;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::config::DeleteMode;
+use crate::journal::{remove_existing, Operation};
+use crate::utils::file::{create_symlink, remove_path};
+use crate::utils::format::human_size;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobKind {
+    Copy,
+    Move,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Done,
+    Cancelled,
+    Failed,
+}
+
+// 終わったジョブの結果
+#[derive(Debug)]
+pub struct JobResult {
+    pub state: JobState,
+    pub operations: Vec<Operation>,
+    pub errors: Vec<String>,
+}
+
+// タスクとUIで共有する進捗
+#[derive(Debug)]
+struct Progress {
+    state: JobState,
+    done_bytes: u64,    // 終わったファイルの合計
+    current_bytes: u64, // コピー中のファイルのコピー済みサイズ
+    total_bytes: u64,
+    operations: Vec<Operation>,
+    errors: Vec<String>,
+}
+
+// 一度にコピーする大きさ (キャンセルと進捗はこの単位で反映される)
+const COPY_CHUNK: usize = 1024 * 1024;
+
+// ----------------------------------------------------------------
+// バックグラウンドで実行するコピー/移動
+// キャンセルされたらコピー中のファイルも途中でやめ，作りかけのコピー先を消す
+// ----------------------------------------------------------------
+pub struct Job {
+    pub job_id: u16,
+    pub kind: JobKind,
+    pub node: String,
+    progress: Arc<Mutex<Progress>>,
+    cancel: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+    reported: bool,
+}
+
+impl Job {
+    pub fn spawn(job_id: u16, kind: JobKind, node: String, pairs: Vec<(PathBuf, PathBuf)>, delete_mode: DeleteMode) -> Job {
+        let progress = Arc::new(Mutex::new(Progress {
+            state: JobState::Running,
+            done_bytes: 0,
+            current_bytes: 0,
+            total_bytes: 0,
+            operations: Vec::new(),
+            errors: Vec::new(),
+        }));
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        let task_progress = progress.clone();
+        let task_cancel = cancel.clone();
+        // tokioのブロッキングタスクは終了時に待たれるので，終了を妨げないスレッドで実行する
+        let handle = thread::spawn(move || {
+            run(kind, pairs, delete_mode, &task_progress, &task_cancel);
+        });
+
+        Job {
+            job_id,
+            kind,
+            node,
+            progress,
+            cancel,
+            handle: Some(handle),
+            reported: false,
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    // スレッドが終わるまで待つ (キャンセルした後なら作りかけのファイルを消し終わるまで)
+    pub fn join(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+
+    pub fn get_state(&self) -> JobState {
+        self.progress.lock().unwrap().state
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.get_state() == JobState::Running
+    }
+
+    // 0~100
+    pub fn get_percent(&self) -> u64 {
+        let progress = self.progress.lock().unwrap();
+        if progress.total_bytes == 0 {
+            return if progress.state == JobState::Running { 0 } else { 100 };
+        }
+        ((progress.done_bytes + progress.current_bytes) * 100 / progress.total_bytes).min(100)
+    }
+
+    // ステータス行に表示する文字列
+    pub fn status(&self) -> String {
+        let progress = self.progress.lock().unwrap();
+        let state = match progress.state {
+            JobState::Running => format!(
+                "{}/{}",
+                human_size(progress.done_bytes + progress.current_bytes),
+                human_size(progress.total_bytes)
+            ),
+            JobState::Done => String::from("done"),
+            JobState::Cancelled => String::from("cancelled"),
+            JobState::Failed => String::from("failed"),
+        };
+        std::mem::drop(progress);
+        format!("[{}] {:?} {} {}% {}", self.job_id, self.kind, self.node, self.get_percent(), state)
+    }
+
+    // 終わっていれば結果を1度だけ返す
+    pub fn take_result(&mut self) -> Option<JobResult> {
+        if self.reported {
+            return None;
+        }
+        let mut progress = self.progress.lock().unwrap();
+        if progress.state == JobState::Running {
+            return None;
+        }
+        self.reported = true;
+        Some(JobResult {
+            state: progress.state,
+            operations: std::mem::take(&mut progress.operations),
+            errors: std::mem::take(&mut progress.errors),
+        })
+    }
+}
+
+// タスク本体 ------------------------------------------------------------
+fn run(kind: JobKind, pairs: Vec<(PathBuf, PathBuf)>, delete_mode: DeleteMode, progress: &Arc<Mutex<Progress>>, cancel: &Arc<AtomicBool>) {
+    let total_bytes = pairs.iter().map(|(from, _)| path_size(from)).sum();
+    progress.lock().unwrap().total_bytes = total_bytes;
+
+    for (from, to) in pairs.iter() {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+        // 上書きするものは先に片付ける (ゴミ箱へ移したものは取り消せる)
+        let trashed = match remove_existing(to, delete_mode) {
+            Ok(op) => op,
+            Err(e) => {
+                progress.lock().unwrap().errors.push(format!("{}: {}", to.to_string_lossy(), e));
+                continue;
+            }
+        };
+        let result = match kind {
+            JobKind::Copy => copy_entry(from, to, progress, cancel),
+            JobKind::Move => move_entry(from, to, progress, cancel),
+        };
+
+        let mut p = progress.lock().unwrap();
+        p.operations.extend(trashed);
+        match result {
+            Ok(true) => {
+                log::info!("{:?} from: {:?}, to: {:?}", kind, from, to);
+                p.operations.push(match kind {
+                    JobKind::Copy => Operation::Copy { from: from.clone(), to: to.clone() },
+                    JobKind::Move => Operation::Move { from: from.clone(), to: to.clone() },
+                });
+            }
+            Ok(false) => {}
+            Err(e) => p.errors.push(format!("{}: {}", from.to_string_lossy(), e)),
+        }
+    }
+
+    let mut p = progress.lock().unwrap();
+    p.state = if cancel.load(Ordering::Relaxed) {
+        JobState::Cancelled
+    } else if p.errors.is_empty() {
+        JobState::Done
+    } else {
+        JobState::Failed
+    };
+}
+
+// 途中でキャンセルされたときは新しく作ったコピー先を消してfalseを返す
+fn copy_entry(from: &Path, to: &Path, progress: &Arc<Mutex<Progress>>, cancel: &Arc<AtomicBool>) -> Result<bool> {
+    let existed = to.symlink_metadata().is_ok();
+    let completed = copy_tree(from, to, progress, cancel)?;
+    if !completed && !existed {
+        let _ = remove_path(to);
+    }
+    Ok(completed)
+}
+
+// 同じファイルシステム内ならrenameで済ませ，できなければコピーしてから元を消す
+fn move_entry(from: &Path, to: &Path, progress: &Arc<Mutex<Progress>>, cancel: &Arc<AtomicBool>) -> Result<bool> {
+    if fs::rename(from, to).is_ok() {
+        progress.lock().unwrap().done_bytes += path_size(to);
+        return Ok(true);
+    }
+    if !copy_entry(from, to, progress, cancel)? {
+        return Ok(false);
+    }
+    remove_path(from)?;
+    Ok(true)
+}
+
+fn copy_tree(from: &Path, to: &Path, progress: &Arc<Mutex<Progress>>, cancel: &Arc<AtomicBool>) -> Result<bool> {
+    if cancel.load(Ordering::Relaxed) {
+        return Ok(false);
+    }
+    let metadata = from.symlink_metadata()?;
+
+    // シンボリックリンクはたどらずにリンクを作り直す
+    if metadata.is_symlink() {
+        if to.symlink_metadata().is_ok() {
+            remove_path(to)?;
+        }
+        create_symlink(&fs::read_link(from)?, to)?;
+        return Ok(true);
+    }
+
+    if metadata.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            if !copy_tree(&entry.path(), &to.join(entry.file_name()), progress, cancel)? {
+                return Ok(false);
+            }
+        }
+        return Ok(true);
+    }
+
+    if !copy_file(from, to, progress, cancel)? {
+        return Ok(false);
+    }
+    fs::set_permissions(to, metadata.permissions())?;
+    let mut p = progress.lock().unwrap();
+    p.current_bytes = 0;
+    p.done_bytes += metadata.len();
+    Ok(true)
+}
+
+// 少しずつコピーして進捗を更新する (キャンセルされたらfalse)
+fn copy_file(from: &Path, to: &Path, progress: &Arc<Mutex<Progress>>, cancel: &Arc<AtomicBool>) -> Result<bool> {
+    let mut reader = fs::File::open(from)?;
+    let mut writer = fs::File::create(to)?;
+    let mut buffer = vec![0u8; COPY_CHUNK];
+    let mut copied: u64 = 0;
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            return Ok(false);
+        }
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            return Ok(true);
+        }
+        writer.write_all(&buffer[..n])?;
+        copied += n as u64;
+        progress.lock().unwrap().current_bytes = copied;
+    }
+}
+
+// シンボリックリンクはたどらない
+fn path_size(path: &Path) -> u64 {
+    let metadata = match path.symlink_metadata() {
+        Ok(m) => m,
+        Err(_) => return 0,
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    match fs::read_dir(path) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| path_size(&e.path())).sum(),
+        Err(_) => 0,
+    }
+}
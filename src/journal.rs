@@ -0,0 +1,315 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::DeleteMode;
+use crate::trash::{Trash, TrashItem};
+use crate::utils::file::{copy_path, create_symlink, move_path, remove_path};
+use crate::utils::path::get_application_root;
+
+const MAX_ENTRIES: usize = 100;
+
+// ----------------------------------------------------------------
+// 元に戻せるファイル操作
+// 戻すときに消すもの(コピーや新規作成したもの)はゴミ箱へ移動する
+// 上書きしたものはゴミ箱へ移してからTrashとして記録しておき，取り消すときに戻す
+// (delete_modeがpermanentのときは上書きしたものは消すだけで記録しない)
+// ----------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Operation {
+    Rename {
+        #[serde(with = "raw_path")] from: PathBuf,
+        #[serde(with = "raw_path")] to: PathBuf,
+    },
+    Move {
+        #[serde(with = "raw_path")] from: PathBuf,
+        #[serde(with = "raw_path")] to: PathBuf,
+    },
+    Copy {
+        #[serde(with = "raw_path")] from: PathBuf,
+        #[serde(with = "raw_path")] to: PathBuf,
+    },
+    NewFile {
+        #[serde(with = "raw_path")] path: PathBuf,
+    },
+    NewFolder {
+        #[serde(with = "raw_path")] path: PathBuf,
+    },
+    Link {
+        #[serde(with = "raw_path")] source: PathBuf,
+        #[serde(with = "raw_path")] dest: PathBuf,
+    },
+    Trash {
+        #[serde(with = "raw_path")] path: PathBuf,
+        name: String, // ゴミ箱の中での名前
+    },
+}
+
+// UTF-8でないパスはバイト列のまま保存する (文字列にすると保存に失敗するか別のパスになる)
+mod raw_path {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::ffi::OsString;
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::path::{Path, PathBuf};
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Text(String),
+        Bytes(Vec<u8>),
+    }
+
+    pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        match path.to_str() {
+            Some(text) => Repr::Text(text.to_string()),
+            None => Repr::Bytes(path.as_os_str().as_bytes().to_vec()),
+        }.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Text(text) => PathBuf::from(text),
+            Repr::Bytes(bytes) => PathBuf::from(OsString::from_vec(bytes)),
+        })
+    }
+}
+
+impl Operation {
+    fn name(&self) -> &str {
+        match self {
+            Operation::Rename { .. } => "Rename",
+            Operation::Move { .. } => "Move",
+            Operation::Copy { .. } => "Copy",
+            Operation::NewFile { .. } => "New file",
+            Operation::NewFolder { .. } => "New folder",
+            Operation::Link { .. } => "Link",
+            Operation::Trash { .. } => "Trash",
+        }
+    }
+
+    fn target(&self) -> &Path {
+        match self {
+            Operation::Rename { to, .. } | Operation::Move { to, .. } | Operation::Copy { to, .. } => to,
+            Operation::NewFile { path } | Operation::NewFolder { path } | Operation::Trash { path, .. } => path,
+            Operation::Link { dest, .. } => dest,
+        }
+    }
+
+    // 操作をもう一度おこなう (ゴミ箱に入れ直したときは名前が変わるので新しい操作を返す)
+    fn apply(&self) -> Result<Operation> {
+        match self {
+            Operation::Rename { from, to } | Operation::Move { from, to } => {
+                ensure_absent(to)?;
+                move_path(from, to)?;
+            }
+            Operation::Copy { from, to } => {
+                ensure_absent(to)?;
+                copy_path(from, to)?;
+            }
+            Operation::NewFile { path } => {
+                ensure_absent(path)?;
+                fs::File::create(path)?;
+            }
+            Operation::NewFolder { path } => {
+                ensure_absent(path)?;
+                fs::create_dir(path)?;
+            }
+            Operation::Link { source, dest } => {
+                ensure_absent(dest)?;
+                create_symlink(source, dest)?;
+            }
+            Operation::Trash { path, .. } => {
+                let item = Trash::for_path(path)?.put(path)?;
+                return Ok(Operation::Trash { path: path.clone(), name: item.name });
+            }
+        }
+        Ok(self.clone())
+    }
+
+    // 操作を取り消す
+    fn revert(&self) -> Result<Operation> {
+        match self {
+            Operation::Rename { from, to } | Operation::Move { from, to } => {
+                ensure_absent(from)?;
+                move_path(to, from)?;
+            }
+            Operation::Copy { to: path, .. } | Operation::NewFile { path } | Operation::NewFolder { path } => {
+                Trash::for_path(path)?.put(path)?;
+            }
+            Operation::Link { dest, .. } => {
+                if !dest.is_symlink() {
+                    return Err(anyhow!("{:?} is not a symbolic link", dest));
+                }
+                fs::remove_file(dest)?;
+            }
+            Operation::Trash { path, name } => {
+                ensure_absent(path)?;
+                let item = TrashItem { name: name.clone(), original_path: path.clone(), deletion_date: String::new() };
+                Trash::for_path(path)?.restore(&item)?;
+            }
+        }
+        Ok(self.clone())
+    }
+}
+
+// 上書きする前に既にあるものを片付ける
+// ゴミ箱へ移したときは取り消して元に戻せるように，上書きする操作より前に記録する操作を返す
+// 完全に削除する設定のときはそのまま消し，元に戻せないので何も返さない
+pub fn remove_existing(path: &Path, delete_mode: DeleteMode) -> Result<Option<Operation>> {
+    if path.symlink_metadata().is_err() {
+        return Ok(None);
+    }
+    match delete_mode {
+        DeleteMode::Trash => {
+            let item = Trash::for_path(path)?.put(path)?;
+            log::info!("Trashed before overwrite: {:?}", path);
+            Ok(Some(Operation::Trash { path: path.to_path_buf(), name: item.name }))
+        }
+        DeleteMode::Permanent => {
+            remove_path(path)?;
+            log::info!("Removed before overwrite: {:?}", path);
+            Ok(None)
+        }
+    }
+}
+
+// 元に戻すときに他のファイルを上書きしないようにする
+fn ensure_absent(path: &Path) -> Result<()> {
+    if path.symlink_metadata().is_ok() {
+        return Err(anyhow!("{:?} is already existed!", path));
+    }
+    Ok(())
+}
+
+// 1回のコマンドでおこなった操作をまとめたもの
+// 上書きする前にゴミ箱へ移したものは数えない
+fn describe(operations: &[Operation]) -> String {
+    let mut main: Vec<&Operation> = operations.iter().filter(|op| !matches!(op, Operation::Trash { .. })).collect();
+    if main.is_empty() {
+        main = operations.iter().collect();
+    }
+    match main.as_slice() {
+        [op] => format!("{} {}", op.name(), op.target().to_string_lossy()),
+        _ => format!("{} {} items", main[0].name(), main.len()),
+    }
+}
+
+// ----------------------------------------------------------------
+// 操作の履歴 (~/.sidebar/journal.yaml に保存して再起動後も使えるようにする)
+// ----------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Journal {
+    undo: Vec<Vec<Operation>>,
+    redo: Vec<Vec<Operation>>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl Journal {
+    // 読み込めないときは空の履歴で始める
+    pub fn load() -> Journal {
+        let path = match get_application_root() {
+            Ok(root) => root.join("journal.yaml"),
+            Err(e) => {
+                log::warn!("Journal is not persisted: {:?}", e);
+                return Journal::default();
+            }
+        };
+
+        let mut journal = match fs::read_to_string(&path) {
+            Ok(yaml) => serde_yaml::from_str(&yaml).unwrap_or_else(|e| {
+                log::warn!("Failed to parse journal: {:?}", e);
+                Journal::default()
+            }),
+            Err(_) => Journal::default(),
+        };
+        journal.path = Some(path);
+        journal
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(ref path) = self.path {
+            fs::write(path, serde_yaml::to_string(self)?)?;
+        }
+        Ok(())
+    }
+
+    pub fn record(&mut self, operations: Vec<Operation>) -> Result<()> {
+        if operations.is_empty() {
+            return Ok(());
+        }
+        log::info!("Journal: {}", describe(&operations));
+        self.undo.push(operations);
+        if self.undo.len() > MAX_ENTRIES {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+        self.save()
+    }
+
+    // 最後の操作を新しいものから順に取り消す
+    // 途中で失敗したときは取り消せた分だけredoに積み，残りはundoに残す
+    pub fn undo(&mut self) -> Result<String> {
+        let mut operations = match self.undo.pop() {
+            Some(ops) => ops,
+            None => return Err(anyhow!("Nothing to undo")),
+        };
+        let description = describe(&operations);
+
+        let mut reverted: Vec<Operation> = Vec::new();
+        let mut result = Ok(());
+        while let Some(op) = operations.pop() {
+            match op.revert() {
+                Ok(op) => reverted.insert(0, op),
+                Err(e) => {
+                    operations.push(op);
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+
+        if !operations.is_empty() {
+            self.undo.push(operations);
+        }
+        if !reverted.is_empty() {
+            self.redo.push(reverted);
+        }
+        self.save()?;
+        result.map(|_| description)
+    }
+
+    // 取り消した操作を古いものから順にやり直す
+    pub fn redo(&mut self) -> Result<String> {
+        let operations = match self.redo.pop() {
+            Some(ops) => ops,
+            None => return Err(anyhow!("Nothing to redo")),
+        };
+        let description = describe(&operations);
+
+        let mut applied: Vec<Operation> = Vec::new();
+        let mut rest = operations.into_iter();
+        let mut result = Ok(());
+        while let Some(op) = rest.next() {
+            match op.apply() {
+                Ok(op) => applied.push(op),
+                Err(e) => {
+                    result = Err(e);
+                    // 失敗したものはredoに戻す
+                    let mut remaining = vec![op];
+                    remaining.extend(rest);
+                    self.redo.push(remaining);
+                    break;
+                }
+            }
+        }
+
+        if !applied.is_empty() {
+            self.undo.push(applied);
+        }
+        self.save()?;
+        result.map(|_| description)
+    }
+}
@@ -0,0 +1,351 @@
+use anyhow::{anyhow, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::action::Action;
+use crate::command::Command;
+
+// 既定のキー割り当て (config.yamlのkeymapで上書きできる)
+pub const DEFAULT_BINDINGS: &[(&str, &str)] = &[
+    ("p", "show_path"),
+    ("u", "update"),
+    ("s", "shell"),
+    ("n", "new_file"),
+    ("N", "new_folder"),
+    ("r", "rename"),
+    ("m", "move"),
+    ("c", "copy"),
+    ("y", "sync"),
+    ("l", "link"),
+    ("space", "toggle_mark"),
+    ("v", "mark_range"),
+    ("a", "mark_all"),
+    ("x", "clear_marks"),
+    ("t", "show_trash"),
+    ("z", "undo"),
+    ("Z", "redo"),
+    ("J", "show_jobs"),
+    ("/", "search"),
+    ("?", "deep_search"),
+    ("]", "search_next"),
+    ("[", "search_prev"),
+    ("P", "toggle_preview"),
+    (".", "toggle_hidden"),
+    ("o", "cycle_sort"),
+    ("O", "reverse_sort"),
+    ("F", "toggle_folders_first"),
+    ("b", "toggle_bookmark"),
+    ("B", "show_bookmarks"),
+    (">", "set_root"),
+    ("<", "parent_root"),
+    ("alt+left", "root_back"),
+    ("alt+right", "root_forward"),
+    ("!", "run"),
+    ("E", "show_output"),
+    ("w", "open_with"),
+    ("enter", "enter"),
+    ("tab", "open_folder"),
+    ("backspace", "delete"),
+    ("esc", "quit"),
+    ("left", "jump_up"),
+    ("right", "jump_down"),
+    ("down", "down"),
+    ("up", "up"),
+];
+
+pub fn default_keymap() -> BTreeMap<String, String> {
+    DEFAULT_BINDINGS.iter().map(|(k, c)| (k.to_string(), c.to_string())).collect()
+}
+
+// ----------------------------------------------------------------
+// 修飾キー込みのキー ("ctrl+alt+x", "shift+up", "N" など)
+// 文字キーのShiftは文字自体に含まれるので取り除いて比較する
+// ----------------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> KeyChord {
+        let mut code = code;
+        let mut modifiers = modifiers & (KeyModifiers::SHIFT | KeyModifiers::CONTROL | KeyModifiers::ALT);
+        match code {
+            KeyCode::Char(_) => modifiers.remove(KeyModifiers::SHIFT),
+            KeyCode::BackTab => modifiers.remove(KeyModifiers::SHIFT),
+            KeyCode::Tab if modifiers.contains(KeyModifiers::SHIFT) => {
+                code = KeyCode::BackTab;
+                modifiers.remove(KeyModifiers::SHIFT);
+            }
+            _ => {}
+        }
+        KeyChord { code, modifiers }
+    }
+
+    pub fn from_event(event: &KeyEvent) -> KeyChord {
+        KeyChord::new(event.code, event.modifiers)
+    }
+
+    pub fn parse(chord: &str) -> Result<KeyChord> {
+        // "ctrl++" のように最後が "+" のキーもあるので，最後の "+" より後ろをキーとする
+        let (mods, key) = match chord.strip_suffix("++") {
+            Some(mods) => (mods, "+"),
+            None => match chord.rsplit_once('+') {
+                Some((mods, key)) if !key.is_empty() => (mods, key),
+                _ => ("", chord),
+            },
+        };
+
+        let mut modifiers = KeyModifiers::NONE;
+        for m in mods.split('+').filter(|m| !m.is_empty()) {
+            match m.to_lowercase().as_str() {
+                "ctrl" | "control" => modifiers.insert(KeyModifiers::CONTROL),
+                "alt" | "meta" | "option" => modifiers.insert(KeyModifiers::ALT),
+                "shift" => modifiers.insert(KeyModifiers::SHIFT),
+                _ => return Err(anyhow!("Unknown modifier '{}' in key '{}'", m, chord)),
+            }
+        }
+
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            // 1文字のキーはそのまま (Shiftが付いていれば大文字にする)
+            (Some(c), None) => {
+                if modifiers.contains(KeyModifiers::SHIFT) {
+                    KeyCode::Char(c.to_ascii_uppercase())
+                } else {
+                    KeyCode::Char(c)
+                }
+            }
+            _ => match key.to_lowercase().as_str() {
+                "enter" | "return" => KeyCode::Enter,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" | "bs" => KeyCode::Backspace,
+                "esc" | "escape" => KeyCode::Esc,
+                "space" => KeyCode::Char(' '),
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" | "ins" => KeyCode::Insert,
+                f if f.starts_with('f') && f[1..].parse::<u8>().is_ok() => KeyCode::F(f[1..].parse::<u8>()?),
+                _ => return Err(anyhow!("Unknown key '{}'", chord)),
+            },
+        };
+        Ok(KeyChord::new(code, modifiers))
+    }
+}
+
+// ----------------------------------------------------------------
+// キーとコマンドの対応表
+// ----------------------------------------------------------------
+#[derive(Debug)]
+pub struct KeyMap {
+    bindings: HashMap<KeyChord, Command>,
+}
+
+impl KeyMap {
+    // 既定の割り当てにconfigのkeymap，actionsのキーの順に重ねる
+    // config.yamlのkeymapには既定の割り当ても書き出されるので，既定と同じ割り当てのキーはactionsで上書きし，
+    // keymapで既定から変えたキーをactionsでも使っているときはエラーにする
+    // 同じキーを別の書き方で2回指定している場合や，知らないキー/コマンドもエラー
+    pub fn new(keymap: &BTreeMap<String, String>, actions: &[Action]) -> Result<KeyMap> {
+        let mut bindings: HashMap<KeyChord, Command> = HashMap::new();
+        for (key, command) in DEFAULT_BINDINGS.iter() {
+            if let Some(command) = parse_command(command)? {
+                bindings.insert(KeyChord::parse(key)?, command);
+            }
+        }
+        let defaults = bindings.clone();
+
+        let mut defined: HashMap<KeyChord, &String> = HashMap::new();
+        let mut customized: HashSet<KeyChord> = HashSet::new();
+        for (key, command) in keymap.iter() {
+            let chord = KeyChord::parse(key).map_err(|e| anyhow!("keymap: {}", e))?;
+            if let Some(other) = defined.insert(chord, key) {
+                return Err(anyhow!("keymap: '{}' and '{}' are the same key", other, key));
+            }
+            let command = parse_command(command).map_err(|e| anyhow!("keymap: {} (key '{}')", e, key))?;
+            if defaults.get(&chord) != command.as_ref() {
+                customized.insert(chord);
+            }
+            match command {
+                Some(command) => bindings.insert(chord, command),
+                None => bindings.remove(&chord),
+            };
+        }
+
+        let mut defined: HashMap<KeyChord, &String> = HashMap::new();
+        for (index, action) in actions.iter().enumerate() {
+            let chord = KeyChord::parse(&action.key).map_err(|e| anyhow!("actions: {} (action '{}')", e, action.name))?;
+            if let Some(other) = defined.insert(chord, &action.name) {
+                return Err(anyhow!("actions: '{}' and '{}' use the same key", other, action.name));
+            }
+            if let Some(command) = bindings.get(&chord) {
+                if customized.contains(&chord) {
+                    return Err(anyhow!("actions: key '{}' of '{}' is already bound in keymap", action.key, action.name));
+                }
+                log::info!("Action '{}' replaces the default binding of '{}' ({:?})", action.name, action.key, command);
+            }
+            bindings.insert(chord, Command::Action(index));
+        }
+
+        Ok(KeyMap { bindings })
+    }
+
+    pub fn get(&self, event: &KeyEvent) -> Option<Command> {
+        self.bindings.get(&KeyChord::from_event(event)).cloned()
+    }
+}
+
+// "none" は割り当ての解除
+fn parse_command(name: &str) -> Result<Option<Command>> {
+    let command = match name {
+        "none" => return Ok(None),
+        "enter" => Command::Enter,
+        "open_folder" => Command::OpenFolder,
+        "new_file" => Command::NewFile,
+        "new_folder" => Command::NewFolder,
+        "copy" => Command::Copy,
+        "move" => Command::Move,
+        "rename" => Command::Rename,
+        "delete" => Command::Delete,
+        "show_path" => Command::ShowPath,
+        "sync" => Command::Sync,
+        "link" => Command::Link,
+        "quit" => Command::Quit,
+        "update" => Command::Update,
+        "shell" => Command::Shell,
+        "up" => Command::Up,
+        "down" => Command::Down,
+        "jump_up" => Command::JumpUp,
+        "jump_down" => Command::JumpDown,
+        "toggle_mark" => Command::ToggleMark,
+        "mark_range" => Command::MarkRange,
+        "mark_all" => Command::MarkAll,
+        "clear_marks" => Command::ClearMarks,
+        "show_trash" => Command::ShowTrash,
+        "undo" => Command::Undo,
+        "redo" => Command::Redo,
+        "show_jobs" => Command::ShowJobs,
+        "search" => Command::Search,
+        "deep_search" => Command::DeepSearch,
+        "search_next" => Command::SearchNext,
+        "search_prev" => Command::SearchPrev,
+        "toggle_preview" => Command::TogglePreview,
+        "toggle_hidden" => Command::ToggleHidden,
+        "cycle_sort" => Command::CycleSort,
+        "reverse_sort" => Command::ReverseSort,
+        "toggle_folders_first" => Command::ToggleFoldersFirst,
+        "toggle_bookmark" => Command::ToggleBookmark,
+        "show_bookmarks" => Command::ShowBookmarks,
+        "set_root" => Command::SetRoot,
+        "parent_root" => Command::ParentRoot,
+        "root_back" => Command::RootBack,
+        "root_forward" => Command::RootForward,
+        "run" => Command::Run,
+        "show_output" => Command::ShowOutput,
+        "open_with" => Command::OpenWith,
+        _ => return Err(anyhow!("Unknown command '{}'", name)),
+    };
+    Ok(Some(command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::load_actions;
+    use crate::config::ActionConfig;
+
+    fn keymap(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(k, c)| (k.to_string(), c.to_string())).collect()
+    }
+
+    fn actions(yaml: &str) -> Vec<Action> {
+        let configs: Vec<ActionConfig> = serde_yaml::from_str(yaml).unwrap();
+        load_actions(&configs).unwrap()
+    }
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn parse_modifiers() {
+        let chord = KeyChord::parse("ctrl+alt+x").unwrap();
+        assert_eq!(chord, KeyChord::new(KeyCode::Char('x'), KeyModifiers::CONTROL | KeyModifiers::ALT));
+        assert_eq!(KeyChord::parse("Control+Meta+x").unwrap(), chord);
+        // 文字キーのShiftは大文字にする
+        assert_eq!(KeyChord::parse("shift+n").unwrap(), KeyChord::parse("N").unwrap());
+        assert_eq!(KeyChord::parse("shift+tab").unwrap(), KeyChord::parse("backtab").unwrap());
+        assert_eq!(KeyChord::parse("ctrl++").unwrap(), KeyChord::new(KeyCode::Char('+'), KeyModifiers::CONTROL));
+        assert_eq!(KeyChord::parse("+").unwrap(), KeyChord::new(KeyCode::Char('+'), KeyModifiers::NONE));
+    }
+
+    #[test]
+    fn parse_named_keys() {
+        assert_eq!(KeyChord::parse("enter").unwrap(), KeyChord::parse("return").unwrap());
+        assert_eq!(KeyChord::parse("Esc").unwrap(), KeyChord::new(KeyCode::Esc, KeyModifiers::NONE));
+        assert_eq!(KeyChord::parse("space").unwrap(), KeyChord::new(KeyCode::Char(' '), KeyModifiers::NONE));
+        assert_eq!(KeyChord::parse("f5").unwrap(), KeyChord::new(KeyCode::F(5), KeyModifiers::NONE));
+        assert_eq!(KeyChord::parse("shift+pageup").unwrap(), KeyChord::new(KeyCode::PageUp, KeyModifiers::SHIFT));
+    }
+
+    #[test]
+    fn parse_rejects_unknown_keys() {
+        assert!(KeyChord::parse("hyper+x").is_err());
+        assert!(KeyChord::parse("ctrl+foo").is_err());
+        assert!(KeyChord::parse("fx").is_err());
+        assert!(KeyChord::parse("").is_err());
+    }
+
+    #[test]
+    fn keymap_overrides_defaults() {
+        let map = KeyMap::new(&keymap(&[("ctrl+j", "down"), ("c", "none")]), &[]).unwrap();
+        assert_eq!(map.get(&key(KeyCode::Char('j'), KeyModifiers::CONTROL)), Some(Command::Down));
+        assert_eq!(map.get(&key(KeyCode::Char('c'), KeyModifiers::NONE)), None);
+        // 端末から届くShift付きの文字も同じキーになる
+        let map = KeyMap::new(&keymap(&[("N", "new_folder")]), &[]).unwrap();
+        assert_eq!(map.get(&key(KeyCode::Char('N'), KeyModifiers::SHIFT)), Some(Command::NewFolder));
+    }
+
+    #[test]
+    fn keymap_rejects_duplicate_spellings() {
+        assert!(KeyMap::new(&keymap(&[("ctrl+a", "copy"), ("control+a", "move")]), &[]).is_err());
+        assert!(KeyMap::new(&keymap(&[("shift+x", "copy"), ("X", "move")]), &[]).is_err());
+    }
+
+    #[test]
+    fn keymap_rejects_unknown_commands() {
+        assert!(KeyMap::new(&keymap(&[("x", "explode")]), &[]).is_err());
+        assert!(KeyMap::new(&keymap(&[("hyper+x", "copy")]), &[]).is_err());
+    }
+
+    #[test]
+    fn actions_replace_default_bindings() {
+        // config.yamlに書き出された既定の割り当てのままでも使える
+        let map = KeyMap::new(&default_keymap(), &actions("[{name: a, key: c, command: echo}]")).unwrap();
+        assert_eq!(map.get(&key(KeyCode::Char('c'), KeyModifiers::NONE)), Some(Command::Action(0)));
+    }
+
+    #[test]
+    fn actions_on_customized_keys_are_rejected() {
+        let custom = keymap(&[("ctrl+j", "down")]);
+        assert!(KeyMap::new(&custom, &actions("[{name: a, key: ctrl+j, command: echo}]")).is_err());
+        // 解除したキーは使える
+        let freed = keymap(&[("c", "none")]);
+        assert!(KeyMap::new(&freed, &actions("[{name: a, key: c, command: echo}]")).is_ok());
+    }
+
+    #[test]
+    fn actions_with_the_same_key_are_rejected() {
+        let duplicated = actions("[{name: a, key: ctrl+j, command: echo}, {name: b, key: control+j, command: echo}]");
+        assert!(KeyMap::new(&BTreeMap::new(), &duplicated).is_err());
+    }
+}
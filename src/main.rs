@@ -1,50 +1,88 @@
+mod action;
 mod app;
+mod bookmark;
 mod color;
 mod command;
 mod config;
+mod filter;
+mod git;
 mod icon;
+mod job;
+mod journal;
+mod keymap;
 mod node;
 mod node_map;
+mod opener;
+mod preview;
+mod screen;
+mod search;
+mod session;
+mod shell;
+mod sync;
+mod theme;
+mod trash;
+mod tree;
 mod utils;
 mod viewer;
+mod watcher;
+mod width;
 
 use anyhow::Result;
-use std::fs::File;
+use std::fs::OpenOptions;
 use std::path::PathBuf;
 use simplelog::{LevelFilter, WriteLogger};
 use log;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use crate::app::App;
 use crate::config::load_config;
+use crate::sync::Sync;
+use crate::tree::TreeFormat;
 use crate::utils::path::{get_cwd_path, resolve_path, get_application_root};
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    // シェルのフックから起動される受信側は画面を使っているsidebarと並行して動くので，ログは追記する
+    if args.sync {
+        init_logger(LevelFilter::Info, true)?;
+        let sync_client = Sync::new(&Sync::resolve_session(args.session))?;
+        sync_client.run(args.signal)?;
+        return Ok(())
+    }
+
     // 各種初期化
-    init_logger(LevelFilter::Info)?;
+    // treeはスクリプトなどから画面を使っているsidebarと並行して呼ばれるので，ログは追記する
+    let is_tree = matches!(args.command, Some(SubCommand::Tree { .. }));
+    init_logger(LevelFilter::Info, is_tree)?;
     log::info!("Application start!");
     let config = load_config()?;
     log::info!("Config: {:?}", config);
-    let args = Args::parse();
     log::info!("Arguments: {:?}", args);
+
+    // 画面を使わずにツリーを出力
+    if let Some(SubCommand::Tree { path, depth, format, all }) = args.command {
+        let root = match path.or(args.path) {
+            Some(p) => resolve_path(p)?,
+            None => get_cwd_path()?
+        };
+        print!("{}", tree::dump(root, config, depth, format, all)?);
+        return Ok(())
+    }
     
-    // if args.sync {
-    //     let sync_client = Sync::new();
-    //     sync_client.run()?;
-    //     return Ok(())
-    // }
-    
+    let sync_client = Sync::new(&Sync::resolve_session(args.session))?;
     let path = match args.path {
         Some(p) => resolve_path(p)?,
         None => get_cwd_path()? 
     };
-    let mut app = App::new(path, config);
+    let mut app = App::new(path, config, sync_client)?;
     app.run()?;
     log::info!("Close application!");
     Ok(())
 }
 
-fn init_logger(level: LevelFilter) -> Result<()> {
+// appendがfalseのときは前回のログを消す
+fn init_logger(level: LevelFilter, append: bool) -> Result<()> {
     let log_path = if cfg!(debug_assertions) {
         PathBuf::from("./app.log")
     } else {
@@ -55,7 +93,7 @@ fn init_logger(level: LevelFilter) -> Result<()> {
     WriteLogger::init(
         level,
         simplelog::Config::default(),
-        File::create(log_path).unwrap(),
+        OpenOptions::new().create(true).write(true).append(append).truncate(!append).open(log_path)?,
     )?;
 
     return Ok(());
@@ -66,6 +104,27 @@ fn init_logger(level: LevelFilter) -> Result<()> {
 struct Args {
     #[arg(short, long, value_parser, help="Specify root path of sidebar")]
     path: Option<PathBuf>,
-    #[arg(short, long, help="Synchronize current working directory with another sidebar")]
+    #[arg(short, long, help="Wait for directories sent by `y` and print them (used by shell hooks)")]
     sync: bool,
+    #[arg(long, help="Session name shared with the shell hook [default: $SIDEBAR_SESSION or \"default\"]")]
+    session: Option<String>,
+    #[arg(long, requires="sync", help="Send SIGUSR1 to this process on every received directory")]
+    signal: Option<i32>,
+    #[command(subcommand)]
+    command: Option<SubCommand>,
+}
+
+#[derive(Subcommand, Debug)]
+enum SubCommand {
+    /// Print the tree without starting the UI
+    Tree {
+        #[arg(value_parser, help="Root path of the tree [default: --path or current directory]")]
+        path: Option<PathBuf>,
+        #[arg(short, long, default_value_t = 1, help="Depth of folders to expand")]
+        depth: usize,
+        #[arg(short, long, value_enum, default_value_t = TreeFormat::Text, help="Output format")]
+        format: TreeFormat,
+        #[arg(short, long, help="Include ignored and hidden files")]
+        all: bool,
+    },
 }
\ No newline at end of file
@@ -1,7 +1,10 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::cmp::Ordering;
+use std::time::SystemTime;
 use uuid::Uuid;
 
+use crate::config::{SortConfig, SortMode};
+
 #[derive(Debug, Clone, PartialOrd, PartialEq, Ord, Eq)]
 pub enum NodeType {
     Folder,
@@ -18,43 +21,60 @@ pub struct Node {
     rank: usize,
     children: Option<Vec<Uuid>>,
     is_open: bool,
+    sort_key: SortKey, // 監視で変更を受け取ったときに読み直す
 }
 
 impl Node {
     pub fn new(id: Uuid, path: PathBuf, node_type: NodeType, rank: usize) -> Node {
+        let sort_key = SortKey::load(&path);
         Node {
             id: id,
-            name: path.file_name().unwrap().to_string_lossy().into_owned(),
+            // "/" には名前が無いのでパスをそのまま使う
+            name: path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| path.to_string_lossy().into_owned()),
             path: path,
             node_type: node_type,
             rank: rank,
             children: None,
             is_open: false,
+            sort_key,
         }
     }
 
-    pub fn sort_fn(a: &Node, b: &Node) -> Ordering {
-        let path_a = a.get_path();
-        let file_type_a = a.get_node_type();
-        let path_b = b.get_path();
-        let file_type_b = b.get_node_type();
-
-        match file_type_a.cmp(&file_type_b) {
-            Ordering::Equal => {
-                // 拡張子で比較
-                let ext_a = path_a.extension();
-                let ext_b = path_b.extension();
-
-                if (ext_a == None) || (ext_b == None) {
-                    return path_a.file_name().cmp(&path_b.file_name());
-                }
+    // ----------------------------------------------------------------
+    // 並べ替えの比較
+    // 種類の分からないもの (壊れたリンクなど) は最後にする
+    // 降順でもフォルダを先にする設定は変わらない．同じ値のときは名前で比べる
+    // ----------------------------------------------------------------
+    pub fn sort_fn(a: &Node, b: &Node, sort: &SortConfig) -> Ordering {
+        let is_unknown_a = a.node_type == NodeType::Unknown;
+        let is_unknown_b = b.node_type == NodeType::Unknown;
+        if is_unknown_a != is_unknown_b {
+            return is_unknown_a.cmp(&is_unknown_b);
+        }
+        if sort.folders_first {
+            let is_file_a = a.node_type != NodeType::Folder;
+            let is_file_b = b.node_type != NodeType::Folder;
+            if is_file_a != is_file_b {
+                return is_file_a.cmp(&is_file_b);
+            }
+        }
 
-                match ext_a.unwrap().cmp(ext_b.unwrap()) {
-                    Ordering::Equal => return path_a.file_name().cmp(&path_b.file_name()),
-                    other => return other,
-                }
+        let ordering = match sort.mode {
+            SortMode::Name => Ordering::Equal,
+            SortMode::Extension => {
+                let ext_a = a.path.extension().map(|e| e.to_string_lossy().into_owned()).unwrap_or_default();
+                let ext_b = b.path.extension().map(|e| e.to_string_lossy().into_owned()).unwrap_or_default();
+                natural_cmp(&ext_a, &ext_b)
             }
-            other => return other,
+            SortMode::Size => a.sort_key.size.cmp(&b.sort_key.size),
+            SortMode::Modified => a.sort_key.modified.cmp(&b.sort_key.modified),
+            SortMode::Created => a.sort_key.created.cmp(&b.sort_key.created),
+        }.then_with(|| natural_cmp(&a.name, &b.name));
+
+        if sort.reverse {
+            ordering.reverse()
+        } else {
+            ordering
         }
     }
 
@@ -85,4 +105,160 @@ impl Node {
     pub fn set_is_open(&mut self, is_open: bool) {
         self.is_open = is_open;
     }
-}
\ No newline at end of file
+    // 大きさや日時が変わっていればtrue
+    pub fn refresh_sort_key(&mut self) -> bool {
+        let sort_key = SortKey::load(&self.path);
+        let changed = sort_key != self.sort_key;
+        self.sort_key = sort_key;
+        changed
+    }
+    pub fn set_rank(&mut self, rank: usize) {
+        self.rank = rank;
+    }
+}
+// 並べ替えに使う値 (ノードを作ったときにファイルシステムから読んでおく)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SortKey {
+    size: u64,
+    modified: Option<SystemTime>,
+    created: Option<SystemTime>,
+}
+
+impl SortKey {
+    fn load(path: &Path) -> SortKey {
+        let metadata = match path.metadata() {
+            Ok(m) => m,
+            Err(_) => return SortKey::default(),
+        };
+        SortKey {
+            // フォルダの大きさは中身と関係ないので比べない
+            size: if metadata.is_dir() { 0 } else { metadata.len() },
+            modified: metadata.modified().ok(),
+            created: metadata.created().ok(),
+        }
+    }
+}
+
+// ----------------------------------------------------------------
+// 人間向けの順番で名前を比べる
+// 大文字小文字を区別せず，数字の並びは数として比べる (file2 < file10)
+// ----------------------------------------------------------------
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut chars_a = a.chars().peekable();
+    let mut chars_b = b.chars().peekable();
+    loop {
+        let (ca, cb) = match (chars_a.peek(), chars_b.peek()) {
+            (None, None) => break,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) => (*ca, *cb),
+        };
+
+        if ca.is_ascii_digit() && cb.is_ascii_digit() {
+            let mut digits_a = String::new();
+            while let Some(c) = chars_a.next_if(|c| c.is_ascii_digit()) {
+                digits_a.push(c);
+            }
+            let mut digits_b = String::new();
+            while let Some(c) = chars_b.next_if(|c| c.is_ascii_digit()) {
+                digits_b.push(c);
+            }
+            // 先頭の0を除いて桁数 -> 文字列で比べれば桁あふれしない
+            let trimmed_a = digits_a.trim_start_matches('0');
+            let trimmed_b = digits_b.trim_start_matches('0');
+            let ordering = trimmed_a.len().cmp(&trimmed_b.len()).then_with(|| trimmed_a.cmp(trimmed_b));
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+            continue;
+        }
+
+        let ordering = ca.to_lowercase().cmp(cb.to_lowercase());
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+        chars_a.next();
+        chars_b.next();
+    }
+    // "file01"と"file1"や大文字小文字だけが違うときも順番を決める
+    a.cmp(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 存在しないパスなので並べ替えの値は既定値 (名前と種類だけで決まる)
+    fn node(name: &str, node_type: NodeType) -> Node {
+        Node::new(Uuid::new_v4(), PathBuf::from("/nonexistent/sidebar-test").join(name), node_type, 1)
+    }
+
+    fn sorted(nodes: &[Node], sort: &SortConfig) -> Vec<String> {
+        let mut nodes = nodes.to_vec();
+        nodes.sort_by(|a, b| Node::sort_fn(a, b, sort));
+        nodes.into_iter().map(|n| n.name).collect()
+    }
+
+    fn sort_config(mode: SortMode, reverse: bool, folders_first: bool) -> SortConfig {
+        SortConfig { mode, reverse, folders_first }
+    }
+
+    #[test]
+    fn natural_cmp_numbers() {
+        assert_eq!(natural_cmp("file2", "file10"), Ordering::Less);
+        assert_eq!(natural_cmp("file10", "file9"), Ordering::Greater);
+        assert_eq!(natural_cmp("a1b2", "a1b10"), Ordering::Less);
+        // 桁あふれする長さでも比べられる
+        assert_eq!(natural_cmp("v99999999999999999999999", "v100000000000000000000000"), Ordering::Less);
+    }
+
+    #[test]
+    fn natural_cmp_leading_zeros() {
+        assert_eq!(natural_cmp("file007", "file10"), Ordering::Less);
+        assert_eq!(natural_cmp("file010", "file9"), Ordering::Greater);
+        // 同じ数のときも順番は決まる
+        assert_ne!(natural_cmp("file01", "file1"), Ordering::Equal);
+        assert_eq!(natural_cmp("file01", "file1"), natural_cmp("file1", "file01").reverse());
+    }
+
+    #[test]
+    fn natural_cmp_ignores_case() {
+        assert_eq!(natural_cmp("apple", "Banana"), Ordering::Less);
+        assert_eq!(natural_cmp("Zebra", "apple"), Ordering::Greater);
+        assert_ne!(natural_cmp("readme", "README"), Ordering::Equal);
+        assert_eq!(natural_cmp("", "a"), Ordering::Less);
+    }
+
+    #[test]
+    fn sort_by_name() {
+        let nodes = [node("file10", NodeType::File), node("File2", NodeType::File), node("file1", NodeType::File)];
+        assert_eq!(sorted(&nodes, &sort_config(SortMode::Name, false, true)), ["file1", "File2", "file10"]);
+        assert_eq!(sorted(&nodes, &sort_config(SortMode::Name, true, true)), ["file10", "File2", "file1"]);
+    }
+
+    #[test]
+    fn sort_by_extension() {
+        let nodes = [node("b.txt", NodeType::File), node("a.rs", NodeType::File), node("c", NodeType::File)];
+        assert_eq!(sorted(&nodes, &sort_config(SortMode::Extension, false, true)), ["c", "a.rs", "b.txt"]);
+    }
+
+    #[test]
+    fn folders_first_is_kept_in_reverse() {
+        let nodes = [
+            node("b", NodeType::File),
+            node("z", NodeType::Folder),
+            node("a", NodeType::Folder),
+            node("c", NodeType::File),
+        ];
+        assert_eq!(sorted(&nodes, &sort_config(SortMode::Name, false, true)), ["a", "z", "b", "c"]);
+        assert_eq!(sorted(&nodes, &sort_config(SortMode::Name, true, true)), ["z", "a", "c", "b"]);
+        assert_eq!(sorted(&nodes, &sort_config(SortMode::Name, false, false)), ["a", "b", "c", "z"]);
+    }
+
+    #[test]
+    fn unknown_nodes_come_last() {
+        let nodes = [node("a", NodeType::Unknown), node("b", NodeType::File), node("c", NodeType::Folder)];
+        assert_eq!(sorted(&nodes, &sort_config(SortMode::Name, false, true)), ["c", "b", "a"]);
+        assert_eq!(sorted(&nodes, &sort_config(SortMode::Name, true, false)), ["c", "b", "a"]);
+    }
+}
@@ -3,22 +3,27 @@
 use anyhow::{anyhow, Result};
 use log;
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::read_dir;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use uuid::Uuid;
-use globset::{Glob, GlobSet, GlobSetBuilder};
 
+use crate::filter::{IgnoreFilter, IGNORE_FILES};
 use crate::node::{Node, NodeType};
 use crate::utils::path::get_file_type;
-use crate::config::Config;
+use crate::config::{Config, SortConfig, SortMode};
+use crate::watcher::{FsChange, FsWatcher};
 
 #[derive(Debug)]
 pub struct NodeMap {
     node_map: HashMap<Uuid, Node>,
     root_id: Uuid,
     config: Arc<Config>,
+    watcher: Option<FsWatcher>,
+    filter: IgnoreFilter,
+    show_hidden: bool, // trueのときは無視するものも表示する
+    sort: SortConfig,
 }
 
 #[allow(dead_code)]
@@ -28,6 +33,7 @@ impl NodeMap {
     // ----------------------------------------------------------------
     pub fn new(root: PathBuf, config: Arc<Config>) -> NodeMap {
         let root_id = Uuid::new_v4();
+        let filter = IgnoreFilter::new(&root, &config.ignore);
         let root_node = Node::new(root_id.clone(), root, NodeType::Folder, 0);
 
         let mut node_map: HashMap<Uuid, Node> = HashMap::new();
@@ -36,10 +42,155 @@ impl NodeMap {
         return NodeMap {
             node_map: node_map,
             root_id: root_id,
-            config: config,
+            watcher: None,
+            filter,
+            show_hidden: false,
+            sort: config.sort,
+            config,
         }
     }
 
+    // ----------------------------------------------------------------
+    // ルートを変更する (新しいルートは開いた状態にする)
+    // 今のルートの中や上のフォルダのときは既にあるノードを使い回して開いているフォルダを残す
+    // ----------------------------------------------------------------
+    pub fn change_root(&mut self, root: PathBuf) -> Result<()> {
+        if !root.is_dir() {
+            return Err(anyhow!("{:?} is not a folder", root))
+        }
+        let current = self.get_path(&self.root_id)?;
+        if root == current {
+            return Ok(());
+        }
+
+        if root.starts_with(&current) {
+            match self.reveal(&root)? {
+                Some(id) => self.narrow_root(&id)?,
+                None => return Err(anyhow!("{:?} is not found", root)),
+            }
+        } else if current.starts_with(&root) {
+            while self.get_path(&self.root_id)? != root {
+                self.widen_root()?;
+            }
+        } else {
+            for id in self.collect_open_ids(&self.root_id)? {
+                self.unwatch(&id)?;
+            }
+            let root_id = Uuid::new_v4();
+            self.node_map.clear();
+            self.node_map.insert(root_id, Node::new(root_id, root.clone(), NodeType::Folder, 0));
+            self.root_id = root_id;
+        }
+
+        self.filter = IgnoreFilter::new(&root, &self.config.ignore);
+        if !self.get_is_open(&self.root_id)? {
+            self.open_and_close_node(&self.get_root_id())?;
+        }
+        Ok(())
+    }
+
+    // 子孫のノードをルートにして，その外のノードを削除する
+    fn narrow_root(&mut self, id: &Uuid) -> Result<()> {
+        let mut keep: HashSet<Uuid> = HashSet::new();
+        let mut stack: Vec<Uuid> = vec![*id];
+        while let Some(keep_id) = stack.pop() {
+            if let Some(children_ids) = self.get_children_ids(&keep_id)? {
+                stack.extend(children_ids);
+            }
+            keep.insert(keep_id);
+        }
+
+        let outside: Vec<Uuid> = self.node_map.keys().filter(|k| !keep.contains(k)).cloned().collect();
+        for outside_id in outside.iter() {
+            if self.get_is_open(outside_id)? {
+                self.unwatch(outside_id)?;
+            }
+        }
+        for outside_id in outside.iter() {
+            self.node_map.remove(outside_id);
+        }
+
+        self.root_id = *id;
+        self.set_ranks(id, 0)
+    }
+
+    // 親フォルダをルートにする (今のルートのノードはそのまま子ノードにする)
+    fn widen_root(&mut self) -> Result<()> {
+        let old_root_id = self.root_id;
+        let old_root = self.get_path(&old_root_id)?;
+        let parent = match old_root.parent() {
+            Some(p) => p.to_path_buf(),
+            None => return Err(anyhow!("{:?} has no parent folder", old_root)),
+        };
+
+        let root_id = Uuid::new_v4();
+        self.node_map.insert(root_id, Node::new(root_id, parent, NodeType::Folder, 0));
+        self.add_children(&root_id)?;
+
+        // add_childrenで作った元のルートのノードを差し替える
+        let mut children_ids = self.get_children_ids(&root_id)?.unwrap_or_default();
+        for child_id in children_ids.iter_mut() {
+            if self.get_path(child_id)? == old_root {
+                self.node_map.remove(child_id);
+                *child_id = old_root_id;
+            }
+        }
+        self.set_children_ids(&root_id, Some(children_ids))?;
+        self.set_is_open(&root_id, true)?;
+        self.root_id = root_id;
+        if self.watcher.is_some() {
+            self.watch(&root_id)?;
+        }
+        self.set_ranks(&root_id, 0)
+    }
+
+    // ルートを変えたときに字下げの深さを付け直す
+    fn set_ranks(&mut self, id: &Uuid, rank: usize) -> Result<()> {
+        match self.node_map.get_mut(id) {
+            Some(node) => node.set_rank(rank),
+            None => return Err(anyhow!("id: {} does not exist", id)),
+        }
+        if let Some(children_ids) = self.get_children_ids(id)? {
+            for child_id in children_ids.iter() {
+                self.set_ranks(child_id, rank + 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    // ----------------------------------------------------------------
+    // 無視するもの (config.ignoreや.gitignoreなど) の表示/非表示を切り替える
+    // ----------------------------------------------------------------
+    pub fn toggle_hidden(&mut self) -> bool {
+        self.show_hidden = !self.show_hidden;
+        self.show_hidden
+    }
+
+    // ----------------------------------------------------------------
+    // 並べ替えの設定
+    // ----------------------------------------------------------------
+    pub fn get_sort(&self) -> SortConfig {
+        self.sort
+    }
+    pub fn set_sort(&mut self, sort: SortConfig) {
+        self.sort = sort;
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        !self.show_hidden && self.filter.is_ignored(path, is_dir)
+    }
+
+    // ----------------------------------------------------------------
+    // ファイルシステムの監視を開始 (開いているフォルダのみ監視する)
+    // ----------------------------------------------------------------
+    pub fn enable_watcher(&mut self) -> Result<()> {
+        self.watcher = Some(FsWatcher::new()?);
+        for id in self.collect_open_ids(&self.get_root_id())? {
+            self.watch(&id)?;
+        }
+        Ok(())
+    }
+
     // ----------------------------------------------------------------
     // 閉じてたら子ノードを追加，開いてたら子ノードを閉じる
     // ----------------------------------------------------------------
@@ -47,6 +198,10 @@ impl NodeMap {
 
         // 開かれているとき
         if self.get_is_open(id)? {
+            // 見えなくなるフォルダの監視を解除
+            for open_id in self.collect_open_ids(id)? {
+                self.unwatch(&open_id)?;
+            }
             // 省メモリモードの時は子ノードを削除
             if self.config.saving_memory {
                 if let Some(children_ids) = self.get_children_ids(id)?{
@@ -65,6 +220,14 @@ impl NodeMap {
                 self.add_children(id)?;
             }
             self.set_is_open(id, true)?;
+
+            // 監視中は閉じていた間の変更を反映してから監視を再開
+            if self.watcher.is_some() {
+                for open_id in self.collect_open_ids(id)? {
+                    self.update_children(&open_id)?;
+                    self.watch(&open_id)?;
+                }
+            }
         }
 
         return Ok(());
@@ -82,6 +245,7 @@ impl NodeMap {
             }
         }
 
+        self.unwatch(id)?;
         self.node_map.remove(id);
         return Ok(());
     }
@@ -133,6 +297,7 @@ impl NodeMap {
     // ルートから順に再帰的に子ノードを更新していく
     // ----------------------------------------------------------------
     pub fn update(&mut self) -> Result<()> {
+        self.filter.clear_cache();
         self._update(&self.get_root_id())?;
         return Ok(());
     }
@@ -143,6 +308,23 @@ impl NodeMap {
     // 新しい子ノードがある場合は追加
     // ----------------------------------------------------------------
     fn _update(&mut self, id: &Uuid) -> Result<()> {
+        // 子ノードを再帰的に更新
+        if let Some(ids) = self.get_children_ids(id)? {
+            for child_id in ids.iter() {
+                if self.get_path(child_id)?.exists() {
+                    self._update(child_id)?;
+                }
+            }
+        }
+        self.update_children(id)
+    }
+
+    // ----------------------------------------------------------------
+    // 1階層分の子ノードをファイルシステムと合わせる
+    // 既にある子ノード  ファイルシステム上に存在しない->削除
+    // 新しい子ノードがある場合は追加
+    // ----------------------------------------------------------------
+    fn update_children(&mut self, id: &Uuid) -> Result<()> {
 
         // ノードの消去 -------------------------------------
         let mut existing_paths: Vec<PathBuf> = Vec::new();
@@ -153,10 +335,10 @@ impl NodeMap {
                 for existed_id in ids.iter() {
                     let existed_path = self.get_path(existed_id)?;
 
-                    // 子ノードのパスが存在している場合は子ノードの更新を再帰的に呼び出し
                     if existed_path.exists() {
-                        // 子ノードを更新
-                        self._update(existed_id)?;
+                        if let Some(node) = self.node_map.get_mut(existed_id) {
+                            node.refresh_sort_key();
+                        }
                         existing_paths.push(existed_path);
                         existing_ids.push(existed_id.clone());
                     }
@@ -166,6 +348,8 @@ impl NodeMap {
                     }
                 }
             }
+            // 開いている空のフォルダは新しく追加されたものだけを探す
+            None if self.get_is_open(id)? => {}
             None => return Ok(()),
         }
 
@@ -201,6 +385,105 @@ impl NodeMap {
         return Ok(());
     }
 
+    // ----------------------------------------------------------------
+    // 監視で受け取った変更を該当するノードにだけ反映
+    // 変更があった場合はtrueを返す
+    // ----------------------------------------------------------------
+    pub fn handle_fs_events(&mut self) -> Result<bool> {
+        let changes = match self.watcher {
+            Some(ref watcher) => watcher.poll(),
+            None => return Ok(false),
+        };
+        if changes.is_empty() {
+            return Ok(false);
+        }
+
+        let mut changed = false;
+        for change in changes {
+            // 無視ファイルが変わったら読み直す
+            if let FsChange::Created(ref path) | FsChange::Removed(ref path) | FsChange::Modified(ref path) = change {
+                if path.file_name().is_some_and(|name| IGNORE_FILES.iter().any(|f| name == *f)) {
+                    self.filter.clear_cache();
+                    changed = true;
+                }
+            }
+            // ログファイル自体の変更で無限に書き込まないようにModifiedは記録しない
+            // 大きさや日時で並べているときだけ並び直す
+            if let FsChange::Modified(ref path) = change {
+                let sorted_by_metadata = matches!(self.sort.mode, SortMode::Size | SortMode::Modified | SortMode::Created);
+                changed |= self.refresh_sort_key(path) && sorted_by_metadata;
+                continue;
+            }
+            log::info!("Fs change: {:?}", change);
+            match change {
+                FsChange::Created(path) => changed |= self.add_node_by_path(&path)?,
+                FsChange::Removed(path) => changed |= self.remove_node_by_path(&path)?,
+                FsChange::Modified(_) => {}
+                FsChange::Rescan => {
+                    self.update()?;
+                    changed = true;
+                }
+            }
+        }
+        Ok(changed)
+    }
+
+    // パスのノードの並べ替えに使う値を読み直す (変わったらtrue)
+    fn refresh_sort_key(&mut self, path: &Path) -> bool {
+        match self.find_id(path).and_then(|id| self.node_map.get_mut(&id)) {
+            Some(node) => node.refresh_sort_key(),
+            None => false,
+        }
+    }
+
+    // 読み込み済みのフォルダにパスのノードを追加
+    fn add_node_by_path(&mut self, path: &Path) -> Result<bool> {
+        let parent_id = match path.parent().and_then(|p| self.find_id(p)) {
+            Some(id) => id,
+            None => return Ok(false),
+        };
+        // 子ノードを読み込んでいない (開いたことがない) フォルダは開いたときに読む
+        let mut children_ids = match self.get_children_ids(&parent_id)? {
+            Some(ids) => ids,
+            None if self.get_is_open(&parent_id)? => Vec::new(),
+            None => return Ok(false),
+        };
+        if self.find_id(path).is_some() || !path.exists() {
+            return Ok(false);
+        }
+        let file_type = match get_file_type(path) {
+            Ok(t) => t,
+            Err(_) => return Ok(false),
+        };
+
+        let id = Uuid::new_v4();
+        let child = Node::new(id, path.to_path_buf(), file_type, self.get_rank(&parent_id)? + 1);
+        self.node_map.insert(id, child);
+        children_ids.push(id);
+        self.set_children_ids(&parent_id, Some(children_ids))?;
+        Ok(true)
+    }
+
+    // パスのノードを親ノードから外して削除
+    fn remove_node_by_path(&mut self, path: &Path) -> Result<bool> {
+        let id = match self.find_id(path) {
+            Some(id) if id != self.root_id => id,
+            _ => return Ok(false),
+        };
+        // 同じ名前で作り直されている場合は消さない
+        if path.exists() {
+            return Ok(false);
+        }
+        if let Some(parent_id) = path.parent().and_then(|p| self.find_id(p)) {
+            if let Some(mut ids) = self.get_children_ids(&parent_id)? {
+                ids.retain(|c| *c != id);
+                self.set_children_ids(&parent_id, Some(ids))?;
+            }
+        }
+        self.delete_node(&id)?;
+        Ok(true)
+    }
+
     // ----------------------------------------------------------------
     // 表示のためにnode_mapを並べる
     // ----------------------------------------------------------------
@@ -237,37 +520,135 @@ impl NodeMap {
     }
     // idのソート
     fn sort_ids(&self, ids: Vec<Uuid>) -> Result<Vec<Uuid>> {
-        let mut nodes = ids.iter().map(|id| self.get_node(id)).collect::<Result<Vec<Node>>>()?;
-        nodes.sort_by(Node::sort_fn);
+        let nodes = ids.iter().map(|id| self.get_node(id)).collect::<Result<Vec<Node>>>()?;
+        let mut nodes = nodes;
+        nodes.sort_by(|a, b| Node::sort_fn(a, b, &self.sort));
         let sorted_id = nodes.iter().map(|node| node.get_id()).collect::<Vec<Uuid>>(); 
         Ok(sorted_id)
     }
-    // config.ignoreにマッチするパターンがあれば抜く
+    // config.ignoreや.gitignoreなどで無視するものを抜く
     fn ignore_ids(&self, ids: Vec<Uuid>) -> Result<Vec<Uuid>>{
-
-        // 無視するパターンの集合を生成
-        let mut builder = GlobSetBuilder::new();
-        for pattern in self.config.ignore.iter() {
-            builder.add(Glob::new(pattern)?);
+        if self.show_hidden {
+            return Ok(ids)
         }
-        let set = builder.build()?;
 
-        // idの中から適合するファイル名をもつidを排除
         let filtered = ids.into_iter().filter(|id| {
-            match self.get_name(id) {
-                Ok(name) => {
-                    if set.is_match(name) {
-                        return false
-                    }
-                    return true
+            match self.node_map.get(id) {
+                Some(node) => {
+                    let is_dir = node.get_node_type() == NodeType::Folder;
+                    !self.filter.is_ignored(&node.get_path(), is_dir)
                 }
-                Err(_) => return false
+                None => false
             }
         }).collect();
 
         return Ok(filtered)
     }
 
+    // ----------------------------------------------------------------
+    // パスからノードを探す (ルートから名前をたどる)
+    // ----------------------------------------------------------------
+    pub fn find_id(&self, path: &Path) -> Option<Uuid> {
+        let root_path = self.get_path(&self.root_id).ok()?;
+        let relative = path.strip_prefix(&root_path).ok()?;
+
+        let mut current = self.root_id;
+        for component in relative.components() {
+            current = self.find_child(&current, component.as_os_str())?;
+        }
+        Some(current)
+    }
+
+    fn find_child(&self, id: &Uuid, name: &std::ffi::OsStr) -> Option<Uuid> {
+        let children_ids = self.get_children_ids(id).ok()??;
+        children_ids.into_iter().find(|child_id| {
+            match self.node_map.get(child_id) {
+                Some(node) => node.get_path().file_name() == Some(name),
+                None => false,
+            }
+        })
+    }
+
+    // ----------------------------------------------------------------
+    // パスの祖先のフォルダを全て開いてノードを表示する (深い検索用)
+    // ----------------------------------------------------------------
+    pub fn reveal(&mut self, path: &Path) -> Result<Option<Uuid>> {
+        let root_path = self.get_path(&self.root_id)?;
+        let relative = match path.strip_prefix(&root_path) {
+            Ok(r) => r.to_path_buf(),
+            Err(_) => return Ok(None),
+        };
+
+        let mut current = self.root_id;
+        for component in relative.components() {
+            if !self.get_is_open(&current)? {
+                self.open_and_close_node(&current)?;
+            }
+            // 読み込んだ後に作られたものは読み直してから探す
+            if self.find_child(&current, component.as_os_str()).is_none() {
+                self.update_children(&current)?;
+            }
+            current = match self.find_child(&current, component.as_os_str()) {
+                Some(id) => id,
+                None => return Ok(None),
+            };
+        }
+        Ok(Some(current))
+    }
+
+    // ----------------------------------------------------------------
+    // 開いているフォルダのパス (セッションの保存用)
+    // ----------------------------------------------------------------
+    pub fn get_open_paths(&self) -> Result<Vec<PathBuf>> {
+        self.collect_open_ids(&self.root_id)?.iter().map(|id| self.get_path(id)).collect()
+    }
+
+    // 保存しておいたフォルダを開き直す (もう存在しないものは飛ばす)
+    pub fn restore_open_paths(&mut self, paths: &[PathBuf]) -> Result<()> {
+        let mut paths: Vec<&PathBuf> = paths.iter().filter(|path| path.is_dir()).collect();
+        // 親フォルダから順に開く
+        paths.sort_by_key(|path| path.components().count());
+        for path in paths {
+            if let Some(id) = self.reveal(path)? {
+                if !self.get_is_open(&id)? {
+                    self.open_and_close_node(&id)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // idのノードとその下で開いているフォルダのidを集める
+    fn collect_open_ids(&self, id: &Uuid) -> Result<Vec<Uuid>> {
+        let mut ids: Vec<Uuid> = Vec::new();
+        if !self.get_is_open(id)? {
+            return Ok(ids);
+        }
+        ids.push(*id);
+        if let Some(children_ids) = self.get_children_ids(id)? {
+            for child_id in children_ids.iter() {
+                ids.extend(self.collect_open_ids(child_id)?);
+            }
+        }
+        Ok(ids)
+    }
+
+    fn watch(&mut self, id: &Uuid) -> Result<()> {
+        let path = self.get_path(id)?;
+        if let Some(ref mut watcher) = self.watcher {
+            watcher.watch(&path)?;
+        }
+        Ok(())
+    }
+
+    fn unwatch(&mut self, id: &Uuid) -> Result<()> {
+        let path = self.get_path(id)?;
+        if let Some(ref mut watcher) = self.watcher {
+            watcher.unwatch(&path);
+        }
+        Ok(())
+    }
+
     // ----------------------------------------------------------------
     // ゲッター
     // ----------------------------------------------------------------
@@ -277,6 +658,9 @@ impl NodeMap {
             None => return Err(anyhow!("id: {} does not exist", id)),
         }
     }
+    pub fn get_config(&self) -> Arc<Config> {
+        self.config.clone()
+    }
     pub fn get_root_id(&self) -> Uuid {
         self.root_id.clone()
     }
@@ -316,6 +700,11 @@ impl NodeMap {
         }
     }
 
+    // ----------------------------------------------------------------
+    pub fn contains(&self, id: &Uuid) -> bool {
+        self.node_map.contains_key(id)
+    }
+
     // ----------------------------------------------------------------
     pub fn get_length(&self) -> usize {
         self.node_map.len()
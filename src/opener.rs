@@ -0,0 +1,274 @@
+use anyhow::{anyhow, Result};
+use duct::{cmd, Expression};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::env;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::{Config, OpenerConfig};
+use crate::shell;
+
+// 拡張子から決めるMIMEタイプ (openersのmimeと比べる)
+const MIME_TYPES: &[(&str, &str)] = &[
+    ("txt", "text/plain"),
+    ("md", "text/markdown"),
+    ("csv", "text/csv"),
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("css", "text/css"),
+    ("js", "text/javascript"),
+    ("json", "application/json"),
+    ("xml", "application/xml"),
+    ("yaml", "application/yaml"),
+    ("yml", "application/yaml"),
+    ("toml", "application/toml"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+    ("ipynb", "application/x-ipynb+json"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("svg", "image/svg+xml"),
+    ("bmp", "image/bmp"),
+    ("ico", "image/x-icon"),
+    ("mp3", "audio/mpeg"),
+    ("wav", "audio/wav"),
+    ("flac", "audio/flac"),
+    ("ogg", "audio/ogg"),
+    ("mp4", "video/mp4"),
+    ("mkv", "video/x-matroska"),
+    ("webm", "video/webm"),
+    ("mov", "video/quicktime"),
+];
+
+// 端末の中で動くエディタ (terminalを省略したときにコマンドの名前で判定する)
+const TERMINAL_EDITORS: &[&str] = &[
+    "vi", "vim", "nvim", "view", "nano", "pico", "hx", "helix", "micro", "kak", "joe", "ne", "mg", "ed",
+];
+
+// この間に終わったものだけ結果を待つ (コマンドが無いなどのすぐわかる失敗を表示する)
+const QUICK_EXIT: Duration = Duration::from_millis(200);
+
+pub fn mime_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_string_lossy().to_lowercase();
+    MIME_TYPES.iter().find(|(e, _)| *e == extension).map(|(_, mime)| *mime)
+}
+
+// ----------------------------------------------------------------
+// ファイルを開くプログラム
+// config.yamlのopenersを上から順に，最後にeditor_command，$VISUAL，$EDITOR，xdg-openを試す
+// ----------------------------------------------------------------
+#[derive(Debug, Clone)]
+pub struct Opener {
+    pub name: String,
+    pub command: String, // {path} などが無ければ最後にパスを付け足す
+    pub terminal: bool, // 画面を端末に戻して実行する
+    patterns: GlobSet, // ファイルの名前と比べる
+    mimes: GlobSet, // "image/*" など
+    any: bool, // patternsもmimeも無ければ全てのファイルを開ける
+}
+
+impl Opener {
+    fn new(config: &OpenerConfig) -> Result<Opener> {
+        if config.command.trim().is_empty() {
+            return Err(anyhow!("command is empty"))
+        }
+        let mut patterns = GlobSetBuilder::new();
+        for pattern in config.patterns.iter() {
+            patterns.add(Glob::new(pattern).map_err(|e| anyhow!("invalid pattern {:?}: {}", pattern, e))?);
+        }
+        let mut mimes = GlobSetBuilder::new();
+        for mime in config.mime.iter() {
+            mimes.add(Glob::new(mime).map_err(|e| anyhow!("invalid mime {:?}: {}", mime, e))?);
+        }
+
+        let name = if config.name.is_empty() { config.command.clone() } else { config.name.clone() };
+        Ok(Opener {
+            name,
+            command: config.command.clone(),
+            terminal: config.terminal.unwrap_or_else(|| is_terminal_editor(&config.command)),
+            patterns: patterns.build()?,
+            mimes: mimes.build()?,
+            any: config.patterns.is_empty() && config.mime.is_empty(),
+        })
+    }
+
+    // 全てのファイルを開けるもの
+    fn fallback(name: &str, command: &str) -> Opener {
+        Opener {
+            name: name.to_string(),
+            command: command.to_string(),
+            terminal: is_terminal_editor(command),
+            patterns: GlobSet::empty(),
+            mimes: GlobSet::empty(),
+            any: true,
+        }
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        if self.any {
+            return true
+        }
+        if path.file_name().is_some_and(|name| self.patterns.is_match(name)) {
+            return true
+        }
+        mime_type(path).is_some_and(|mime| self.mimes.is_match(mime))
+    }
+
+    // "code" のように置き換える文字が無ければ "code 'path'" にする
+    pub fn command_line(&self, path: &Path) -> String {
+        let dir = path.parent().unwrap_or(path);
+        let expanded = shell::expand(&self.command, path, dir, &[path.to_path_buf()]);
+        if expanded == self.command {
+            format!("{} {}", self.command, shell::quote(&path.to_string_lossy()))
+        } else {
+            expanded
+        }
+    }
+
+    // 端末を渡して実行するとき用 (入出力はそのまま)
+    pub fn expression(&self, path: &Path) -> Expression {
+        cmd!("sh", "-c", self.command_line(path))
+    }
+
+    // 終わるのを待たずに戻る (code --wait などでも画面を止めない)
+    // すぐに失敗したときは標準エラー出力をエラーにし，後で失敗したときはログに残す
+    pub fn open(&self, path: &Path) -> Result<()> {
+        let handle = self.expression(path)
+            .stdin_null()
+            .stdout_null()
+            .stderr_capture()
+            .unchecked()
+            .start()?;
+        let started = Instant::now();
+        while started.elapsed() < QUICK_EXIT {
+            if let Some(output) = handle.try_wait()? {
+                return check_output(&self.name, output);
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        // 終了を待つスレッドで後片付けする
+        let name = self.name.clone();
+        thread::spawn(move || {
+            let result = handle.wait().map_err(|e| e.into()).and_then(|output| check_output(&name, output));
+            if let Err(e) = result {
+                log::warn!("Opener failed: {}", e);
+            }
+        });
+        Ok(())
+    }
+}
+
+fn check_output(name: &str, output: &std::process::Output) -> Result<()> {
+    if output.status.success() {
+        return Ok(())
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    match stderr.lines().find(|line| !line.trim().is_empty()) {
+        Some(line) => Err(anyhow!("{}: {}", name, line.trim())),
+        None => Err(anyhow!("{}: {}", name, output.status)),
+    }
+}
+
+// "vim -p" や "/usr/bin/nano" はコマンドの名前で，emacsは -nw / -t のときだけ端末で動くとみなす
+fn is_terminal_editor(command: &str) -> bool {
+    let mut words = command.split_whitespace();
+    let program = match words.next() {
+        Some(program) => program.rsplit('/').next().unwrap_or(program),
+        None => return false,
+    };
+    match program {
+        "emacs" | "emacsclient" => words.any(|w| matches!(w, "-nw" | "--no-window-system" | "-t" | "--tty")),
+        _ => TERMINAL_EDITORS.contains(&program),
+    }
+}
+
+// 設定に誤りがあればエラー
+pub fn load_openers(config: &Config) -> Result<Vec<Opener>> {
+    let mut openers: Vec<Opener> = Vec::new();
+    for (i, opener) in config.openers.iter().enumerate() {
+        let opener = Opener::new(opener).map_err(|e| anyhow!("openers[{}]: {}", i, e))?;
+        openers.push(opener);
+    }
+
+    // 以前の設定 (editor_command) も全てのファイルに使う
+    if !config.editor_command.trim().is_empty() {
+        openers.push(Opener::fallback("editor_command", &config.editor_command));
+    }
+    for var in ["VISUAL", "EDITOR"] {
+        if let Some(command) = env::var(var).ok().filter(|c| !c.trim().is_empty()) {
+            openers.push(Opener::fallback(&format!("${}", var), &command));
+        }
+    }
+    let system = if cfg!(target_os = "macos") { "open" } else { "xdg-open" };
+    openers.push(Opener::fallback(system, system));
+    Ok(openers)
+}
+
+// pathを開けるもの (同じコマンドは最初のものだけ)
+pub fn candidates<'a>(openers: &'a [Opener], path: &Path) -> Vec<&'a Opener> {
+    let mut found: Vec<&Opener> = Vec::new();
+    for opener in openers.iter().filter(|o| o.matches(path)) {
+        if !found.iter().any(|o| o.command == opener.command) {
+            found.push(opener);
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opener(yaml: &str) -> Opener {
+        Opener::new(&serde_yaml::from_str::<OpenerConfig>(yaml).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn terminal_editors_are_detected_by_program_name() {
+        assert!(is_terminal_editor("vim -p"));
+        assert!(is_terminal_editor("/usr/bin/nano"));
+        assert!(is_terminal_editor("emacs -nw"));
+        assert!(!is_terminal_editor("emacs"));
+        assert!(!is_terminal_editor("code --wait"));
+        assert!(!is_terminal_editor("less"));
+        assert!(!is_terminal_editor(""));
+    }
+
+    #[test]
+    fn mime_type_uses_lowercase_extension() {
+        assert_eq!(mime_type(Path::new("a/photo.JPG")), Some("image/jpeg"));
+        assert_eq!(mime_type(Path::new("notes.md")), Some("text/markdown"));
+        assert_eq!(mime_type(Path::new("Makefile")), None);
+        assert_eq!(mime_type(Path::new("archive.unknown")), None);
+    }
+
+    #[test]
+    fn candidates_keep_order_and_skip_duplicate_commands() {
+        let openers = vec![
+            opener("{patterns: ['*.md'], command: code}"),
+            opener("{mime: ['image/*'], command: feh}"),
+            opener("{patterns: ['*.md', '*.txt'], command: code}"),
+            Opener::fallback("xdg-open", "xdg-open"),
+        ];
+        let names = |path: &str| -> Vec<String> {
+            candidates(&openers, Path::new(path)).iter().map(|o| o.name.clone()).collect()
+        };
+        assert_eq!(names("README.md"), vec!["code", "xdg-open"]);
+        assert_eq!(names("icon.png"), vec!["feh", "xdg-open"]);
+        assert_eq!(names("memo.txt"), vec!["code", "xdg-open"]);
+        assert_eq!(names("main.rs"), vec!["xdg-open"]);
+    }
+
+    #[test]
+    fn command_line_appends_the_path_without_placeholders() {
+        assert_eq!(opener("{command: code}").command_line(Path::new("/tmp/a b.md")), "code '/tmp/a b.md'");
+        assert_eq!(opener("{command: 'feh {path}'}").command_line(Path::new("/tmp/x.png")), "feh '/tmp/x.png'");
+    }
+}
@@ -0,0 +1,106 @@
+use chrono::{DateTime, Local};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use crate::utils::format::human_size;
+
+// ----------------------------------------------------------------
+// カーソル上のノードのプレビュー
+// 1行目以降にメタデータ，空行のあとにファイルの中身かフォルダの一覧を並べる
+// ----------------------------------------------------------------
+pub fn build(path: &Path, max_bytes: u64, max_lines: usize) -> Vec<String> {
+    let metadata = match path.symlink_metadata() {
+        Ok(m) => m,
+        Err(e) => return vec![format!("{}", e)],
+    };
+
+    let mut lines: Vec<String> = Vec::new();
+    if metadata.is_symlink() {
+        if let Ok(target) = fs::read_link(path) {
+            lines.push(format!("-> {}", target.to_string_lossy()));
+        }
+    }
+    // リンク先の情報を表示する
+    let metadata = fs::metadata(path).unwrap_or(metadata);
+    lines.push(format!("{}  {}", permissions(&metadata), human_size(metadata.len())));
+    if let Ok(modified) = metadata.modified() {
+        lines.push(DateTime::<Local>::from(modified).format("%Y-%m-%d %H:%M:%S").to_string());
+    }
+    lines.push(String::new());
+
+    let body = if metadata.is_dir() {
+        list_dir(path, max_lines)
+    } else {
+        read_text(path, max_bytes, max_lines)
+    };
+    match body {
+        Ok(body) => lines.extend(body),
+        Err(e) => lines.push(format!("({})", e)),
+    }
+    lines
+}
+
+// フォルダの中身 (フォルダが先，名前順)
+fn list_dir(path: &Path, max_lines: usize) -> std::io::Result<Vec<String>> {
+    let mut entries: Vec<(bool, String)> = fs::read_dir(path)?
+        .filter_map(|e| e.ok())
+        .map(|e| (!e.path().is_dir(), e.file_name().to_string_lossy().into_owned()))
+        .collect();
+    if entries.is_empty() {
+        return Ok(vec![String::from("(empty)")]);
+    }
+    entries.sort();
+    let total = entries.len();
+    let mut lines: Vec<String> = entries.into_iter().take(max_lines).map(|(is_file, name)| {
+        if is_file { name } else { format!("{}/", name) }
+    }).collect();
+    if total > lines.len() {
+        lines.push(format!("... {} more", total - lines.len()));
+    }
+    Ok(lines)
+}
+
+// テキストファイルの先頭 (NULを含むかUTF-8として読めないものはバイナリとみなす)
+fn read_text(path: &Path, max_bytes: u64, max_lines: usize) -> std::io::Result<Vec<String>> {
+    let mut buf: Vec<u8> = Vec::new();
+    fs::File::open(path)?.take(max_bytes).read_to_end(&mut buf)?;
+
+    let text = match std::str::from_utf8(&buf) {
+        Ok(text) => text,
+        // max_bytesで文字の途中で切れた場合はそこまで
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&buf[..e.valid_up_to()]).unwrap_or_default(),
+        Err(_) => return Ok(vec![String::from("(binary file)")]),
+    };
+    if text.contains('\0') {
+        return Ok(vec![String::from("(binary file)")]);
+    }
+
+    Ok(text.lines().take(max_lines).map(|line| {
+        line.replace('\t', "    ").chars().filter(|c| !c.is_control()).collect()
+    }).collect())
+}
+
+#[cfg(unix)]
+fn permissions(metadata: &fs::Metadata) -> String {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = metadata.permissions().mode();
+    let kind = if metadata.is_dir() { 'd' } else { '-' };
+    let mut s = String::from(kind);
+    for shift in [6, 3, 0] {
+        let bits = (mode >> shift) & 0o7;
+        s.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        s.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        s.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    s
+}
+
+#[cfg(not(unix))]
+fn permissions(metadata: &fs::Metadata) -> String {
+    if metadata.permissions().readonly() {
+        String::from("readonly")
+    } else {
+        String::from("writable")
+    }
+}
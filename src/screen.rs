@@ -0,0 +1,56 @@
+use anyhow::Result;
+use crossterm::cursor::MoveTo;
+use crossterm::style::Print;
+use crossterm::terminal::{BeginSynchronizedUpdate, Clear, ClearType, EndSynchronizedUpdate};
+use crossterm::queue;
+use std::io::{stdout, Write};
+
+// ----------------------------------------------------------------
+// 前回描画した画面を覚えておき，変わった行だけを書き換える
+// 書き換えは同期更新 (対応していない端末では無視される) で囲み，途中の状態を見せない
+// ----------------------------------------------------------------
+#[derive(Debug, Default)]
+pub struct Screen {
+    lines: Vec<String>,
+    width: usize,
+}
+
+impl Screen {
+    pub fn new() -> Screen {
+        Screen::default()
+    }
+
+    // 次の描画で全ての行を書き直す (別のプログラムが端末を使った後など)
+    pub fn invalidate(&mut self) {
+        self.lines.clear();
+    }
+
+    // frameは画面の1行ごとの内容 (端末の高さと同じ数)
+    pub fn draw(&mut self, frame: Vec<String>, width: usize) -> Result<()> {
+        let mut out = stdout();
+
+        // 端末の大きさが変わったときは前回の内容が崩れているので消してから描く
+        let resized = width != self.width || frame.len() != self.lines.len();
+        if !resized && frame == self.lines {
+            return Ok(());
+        }
+
+        queue!(out, BeginSynchronizedUpdate)?;
+        if resized {
+            queue!(out, Clear(ClearType::All))?;
+        }
+        for (row, line) in frame.iter().enumerate() {
+            if !resized && self.lines.get(row) == Some(line) {
+                continue;
+            }
+            // 行末まで書いた後に消すと最後の文字も消えるので先に消す
+            queue!(out, MoveTo(0, row as u16), Clear(ClearType::CurrentLine), Print(line))?;
+        }
+        queue!(out, EndSynchronizedUpdate)?;
+        out.flush()?;
+
+        self.lines = frame;
+        self.width = width;
+        Ok(())
+    }
+}
@@ -0,0 +1,189 @@
+use std::fs::read_dir;
+use std::path::{Path, PathBuf};
+
+// 深い検索でたどるエントリ数の上限
+pub const WALK_LIMIT: usize = 20000;
+
+// 検索語と一致した結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    pub score: i64,
+    pub positions: Vec<usize>, // 一致した文字の位置 (文字単位)
+}
+
+// ----------------------------------------------------------------
+// あいまい検索
+// 検索語の文字が順番通りに含まれていれば一致とする
+// 大文字を含むときだけ大文字小文字を区別する (smart case)
+// 連続した一致や単語の先頭での一致ほどスコアが高い
+// ----------------------------------------------------------------
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<Match> {
+    let case_sensitive = pattern.chars().any(|c| c.is_uppercase());
+    let normalize = |c: char| if case_sensitive { c } else { c.to_ascii_lowercase() };
+    let pattern: Vec<char> = pattern.chars().map(normalize).collect();
+    let original: Vec<char> = text.chars().collect();
+    let text: Vec<char> = original.iter().map(|c| normalize(*c)).collect();
+
+    if pattern.is_empty() {
+        return Some(Match { score: 0, positions: Vec::new() });
+    }
+
+    // 先頭の文字の出現位置ごとに前から貪欲に探して一番良いものを選ぶ
+    let mut best: Option<Match> = None;
+    for start in 0..text.len() {
+        if text[start] != pattern[0] {
+            continue;
+        }
+        let mut positions = vec![start];
+        let mut j = start + 1;
+        for p in pattern[1..].iter() {
+            while j < text.len() && text[j] != *p {
+                j += 1;
+            }
+            if j == text.len() {
+                break;
+            }
+            positions.push(j);
+            j += 1;
+        }
+        if positions.len() != pattern.len() {
+            // これより後ろから始めても見つからない
+            break;
+        }
+
+        let score = score(&original, &positions);
+        if best.as_ref().is_none_or(|b| score > b.score) {
+            best = Some(Match { score, positions });
+        }
+    }
+    best
+}
+
+fn score(text: &[char], positions: &[usize]) -> i64 {
+    let mut score: i64 = 0;
+    for (i, &pos) in positions.iter().enumerate() {
+        score += 10;
+        // 単語の先頭 (区切り文字の直後や小文字->大文字)
+        let boundary = pos == 0 || {
+            let prev = text[pos - 1];
+            "/_-. ".contains(prev) || (prev.is_lowercase() && text[pos].is_uppercase())
+        };
+        if boundary {
+            score += 8;
+        }
+        if i > 0 {
+            let gap = pos - positions[i - 1] - 1;
+            if gap == 0 {
+                score += 6;
+            } else {
+                score -= gap.min(10) as i64;
+            }
+        }
+    }
+    // 短いものを優先
+    score - (text.len() as i64) / 8
+}
+
+// ----------------------------------------------------------------
+// 検索対象の文字列
+// 検索語に "/" が含まれるときはルートからの相対パス，それ以外は名前
+// 返り値の2つ目は名前の先頭が対象文字列の何文字目か
+// ----------------------------------------------------------------
+pub fn target_text(pattern: &str, name: &str, relative: &Path) -> (String, usize) {
+    if pattern.contains('/') && !relative.as_os_str().is_empty() {
+        let relative = relative.to_string_lossy().into_owned();
+        let offset = relative.chars().count() - name.chars().count();
+        return (relative, offset);
+    }
+    (name.to_string(), 0)
+}
+
+// ----------------------------------------------------------------
+// 開いていないフォルダも含めてルート以下のパスを集める (深い検索用)
+// is_ignoredがtrueを返すもの (パス, フォルダか) は中に入らない．シンボリックリンクはたどらない
+// 上限に達したときはtrueを返す
+// ----------------------------------------------------------------
+pub fn walk<F: Fn(&Path, bool) -> bool>(root: &Path, is_ignored: F) -> (Vec<PathBuf>, bool) {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    let mut stack: Vec<PathBuf> = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let entries = match read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            if is_ignored(&path, is_dir) {
+                continue;
+            }
+            if paths.len() >= WALK_LIMIT {
+                return (paths, true);
+            }
+            if is_dir {
+                stack.push(path.clone());
+            }
+            paths.push(path);
+        }
+    }
+    (paths, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(pattern: &str, text: &str) -> Option<Vec<usize>> {
+        fuzzy_match(pattern, text).map(|m| m.positions)
+    }
+
+    fn score(pattern: &str, text: &str) -> i64 {
+        fuzzy_match(pattern, text).unwrap().score
+    }
+
+    #[test]
+    fn match_positions() {
+        assert_eq!(positions("abc", "abc"), Some(vec![0, 1, 2]));
+        assert_eq!(positions("mrs", "main.rs"), Some(vec![0, 5, 6]));
+        // 連続して一致する位置を選ぶ
+        assert_eq!(positions("rs", "readme.rs"), Some(vec![7, 8]));
+        // 位置は文字単位
+        assert_eq!(positions("md", "日本語.md"), Some(vec![4, 5]));
+        assert_eq!(positions("", "abc"), Some(vec![]));
+    }
+
+    #[test]
+    fn no_match() {
+        assert_eq!(fuzzy_match("ba", "abc"), None);
+        assert_eq!(fuzzy_match("abcd", "abc"), None);
+        assert_eq!(fuzzy_match("a", ""), None);
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(fuzzy_match("readme", "README.md").is_some());
+        assert!(fuzzy_match("README", "readme.md").is_none());
+        assert!(fuzzy_match("Read", "README.md").is_none());
+        assert!(fuzzy_match("Read", "ReadMe.md").is_some());
+    }
+
+    #[test]
+    fn ranking() {
+        // 連続した一致 > 離れた一致
+        assert!(score("main", "main.rs") > score("main", "my_animation.rs"));
+        // 単語の先頭 > 途中
+        assert!(score("conf", "app_config.rs") > score("conf", "nonconfig.rs"));
+        assert!(score("ct", "CamelTree") > score("ct", "cattle"));
+        // 同じ一致なら短い方
+        assert!(score("lib", "lib.rs") > score("lib", "lib_with_a_long_name.rs"));
+    }
+
+    #[test]
+    fn target_text_for_pattern() {
+        let relative = Path::new("src/ui/main.rs");
+        assert_eq!(target_text("main", "main.rs", relative), ("main.rs".to_string(), 0));
+        assert_eq!(target_text("ui/main", "main.rs", relative), ("src/ui/main.rs".to_string(), 7));
+        // ルート自体は名前で探す
+        assert_eq!(target_text("a/b", "root", Path::new("")), ("root".to_string(), 0));
+    }
+}
@@ -0,0 +1,53 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::utils::path::get_application_root;
+
+// ----------------------------------------------------------------
+// 終了したときの表示状態 (ルートのパスごとに ~/.sidebar/sessions.yaml に保存)
+// ----------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Session {
+    pub open_paths: Vec<PathBuf>, // 開いていたフォルダ
+    pub cursor: Option<PathBuf>,  // カーソルがあったノード
+    pub scroll: usize,            // 表示していた先頭の行
+}
+
+type Sessions = BTreeMap<PathBuf, Session>;
+
+impl Session {
+    // 保存されていなければNone
+    pub fn load(root: &Path) -> Option<Session> {
+        let sessions = match read_sessions() {
+            Ok(sessions) => sessions,
+            Err(e) => {
+                log::warn!("Failed to load session: {:?}", e);
+                return None;
+            }
+        };
+        sessions.get(root).cloned()
+    }
+
+    // 他のルートのものは残したまま書き換える
+    pub fn save(&self, root: &Path) -> Result<()> {
+        let mut sessions = read_sessions().unwrap_or_default();
+        sessions.insert(root.to_path_buf(), self.clone());
+        fs::write(get_sessions_path()?, serde_yaml::to_string(&sessions)?)?;
+        Ok(())
+    }
+}
+
+fn get_sessions_path() -> Result<PathBuf> {
+    Ok(get_application_root()?.join("sessions.yaml"))
+}
+
+fn read_sessions() -> Result<Sessions> {
+    let path = get_sessions_path()?;
+    if !path.exists() {
+        return Ok(Sessions::new());
+    }
+    Ok(serde_yaml::from_str(&fs::read_to_string(path)?)?)
+}
@@ -0,0 +1,137 @@
+use anyhow::Result;
+use duct::cmd;
+use std::path::{Path, PathBuf};
+
+use crate::width::strip_ansi;
+
+// 出力パネルに残す最大の行数
+const MAX_OUTPUT_LINES: usize = 10000;
+
+// ----------------------------------------------------------------
+// コマンドの {path} などを選択しているノードに置き換える
+// 置き換えた値はシェルに渡せるように引用符で囲む
+//   {path}  カーソル上のノードのパス
+//   {dir}   カーソル上のフォルダ (ファイルなら親フォルダ)
+//   {name}  カーソル上のノードの名前
+//   {stem}  名前から拡張子を除いたもの
+//   {paths} マークしたノード全て (マークが無ければカーソル上のノード)
+// ----------------------------------------------------------------
+pub fn expand(template: &str, path: &Path, dir: &Path, targets: &[PathBuf]) -> String {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let stem = path.file_stem().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let values = [
+        ("{paths}", targets.iter().map(|p| quote(&p.to_string_lossy())).collect::<Vec<String>>().join(" ")),
+        ("{path}", quote(&path.to_string_lossy())),
+        ("{dir}", quote(&dir.to_string_lossy())),
+        ("{name}", quote(&name)),
+        ("{stem}", quote(&stem)),
+    ];
+
+    // 置き換えた値の中の {..} はそのまま残すように先頭から1回だけ読む
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match values.iter().find(|(key, _)| rest.starts_with(key)) {
+            Some((key, value)) => {
+                expanded.push_str(value);
+                rest = &rest[key.len()..];
+            }
+            None => {
+                expanded.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+// シングルクォートで囲む (中のシングルクォートは '\'' にする)
+pub fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+// コマンドの実行結果
+#[derive(Debug, Clone)]
+pub struct Output {
+    pub code: Option<i32>, // シグナルで終了したときはNone
+    pub lines: Vec<String>, // 標準出力と標準エラー出力 (出てきた順)
+}
+
+impl Output {
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+// dirでsh -cを使って実行し，終わるまで待つ (入力は受け付けない)
+pub fn run(command_line: &str, dir: &Path) -> Result<Output> {
+    let output = cmd!("sh", "-c", command_line)
+        .dir(dir)
+        .stdin_null()
+        .stderr_to_stdout()
+        .stdout_capture()
+        .unchecked()
+        .run()?;
+
+    // パネルに表示できるように色やタブを取り除く
+    let text = strip_ansi(&String::from_utf8_lossy(&output.stdout));
+    let mut lines: Vec<String> = text.lines().map(|line| {
+        line.replace('\t', "    ").chars().filter(|c| !c.is_control()).collect()
+    }).collect();
+    if lines.len() > MAX_OUTPUT_LINES {
+        let omitted = lines.len() - MAX_OUTPUT_LINES;
+        lines.drain(..omitted);
+        lines.insert(0, format!("... ({} lines omitted)", omitted));
+    }
+
+    Ok(Output {
+        code: output.status.code(),
+        lines,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_special_characters() {
+        assert_eq!(quote("a b"), "'a b'");
+        assert_eq!(quote("it's"), "'it'\\''s'");
+        assert_eq!(quote("$HOME `id`"), "'$HOME `id`'");
+        assert_eq!(quote(""), "''");
+    }
+
+    #[test]
+    fn expand_placeholders() {
+        let path = Path::new("/tmp/my dir/note.tar.gz");
+        let dir = Path::new("/tmp/my dir");
+        let targets = [PathBuf::from("/tmp/a"), PathBuf::from("/tmp/b c")];
+        assert_eq!(expand("ls {dir}", path, dir, &targets), "ls '/tmp/my dir'");
+        assert_eq!(expand("{name} {stem}", path, dir, &targets), "'note.tar.gz' 'note.tar'");
+        assert_eq!(expand("rm {paths}", path, dir, &targets), "rm '/tmp/a' '/tmp/b c'");
+        // 知らない {..} はそのまま
+        assert_eq!(expand("echo {x} {", path, dir, &targets), "echo {x} {");
+    }
+
+    #[test]
+    fn expanded_values_are_not_expanded_again() {
+        let path = Path::new("/tmp/{path}");
+        assert_eq!(expand("echo {name}", path, Path::new("/tmp"), &[]), "echo '{path}'");
+    }
+
+    #[test]
+    fn expanded_names_reach_the_command_as_is() {
+        // シェルに解釈されずに名前がそのまま引数になる
+        for name in ["a b", "it's", "$(touch injected)", "`id`", "$HOME", "x'; echo injected; '", "semi;colon"] {
+            let path = Path::new("/nonexistent").join(name);
+            let command_line = expand("printf '%s\\n' {name}", &path, Path::new("/nonexistent"), std::slice::from_ref(&path));
+            let output = run(&command_line, Path::new("/")).unwrap();
+            assert!(output.success());
+            assert_eq!(output.lines, [name]);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::utils::path::get_application_root;
+
+// ----------------------------------------------------------------
+// 別のシェルとカレントディレクトリを同期する
+// 送信側 (sidebar本体) : ~/.sidebar/sync/<session>.sock にパスを送る
+// 受信側 (sidebar --sync) : ソケットで待ち受けて受け取ったパスを標準出力に書き出す
+// ----------------------------------------------------------------
+pub struct Sync {
+    session: String,
+    socket_path: PathBuf,
+}
+
+impl Sync {
+    pub fn new(session: &str) -> Result<Sync> {
+        if session.is_empty() || session.contains('/') {
+            return Err(anyhow!("Invalid session name: {}", session));
+        }
+        let sync_dir = get_application_root()?.join("sync");
+        if !sync_dir.exists() {
+            fs::create_dir_all(&sync_dir)?;
+        }
+
+        Ok(Sync {
+            session: session.to_string(),
+            socket_path: sync_dir.join(format!("{}.sock", session)),
+        })
+    }
+
+    // セッション名は --session > 環境変数SIDEBAR_SESSION > "default" の順で決める
+    pub fn resolve_session(session: Option<String>) -> String {
+        match session {
+            Some(s) => s,
+            None => std::env::var("SIDEBAR_SESSION").unwrap_or(String::from("default")),
+        }
+    }
+
+    // 送信側 -------------------------------------------------------
+    #[cfg(unix)]
+    pub fn send(&self, path: &Path) -> Result<()> {
+        use std::os::unix::ffi::OsStrExt;
+        use std::os::unix::net::UnixDatagram;
+
+        let socket = UnixDatagram::unbound()?;
+        match socket.send_to(path.as_os_str().as_bytes(), &self.socket_path) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                log::warn!("Sync failed: {:?}", e);
+                Err(anyhow!("No shell is listening on session '{}'", self.session))
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn send(&self, _path: &Path) -> Result<()> {
+        Err(anyhow!("Sync is only supported on Unix"))
+    }
+
+    // 受信側 -------------------------------------------------------
+    // signal_pidが指定されているときは受信するたびにSIGUSR1を送る
+    // そのプロセスが終了していたら受信側も終了する
+    #[cfg(unix)]
+    pub fn run(&self, signal_pid: Option<i32>) -> Result<()> {
+        use std::io::{stdout, ErrorKind, Write};
+        use std::os::unix::ffi::OsStrExt;
+        use std::os::unix::fs::MetadataExt;
+        use std::os::unix::net::UnixDatagram;
+        use std::time::Duration;
+
+        // 他のシェルが待ち受けているソケットは奪わない (前回の残りなら削除)
+        if self.socket_path.exists() {
+            if UnixDatagram::unbound()?.connect(&self.socket_path).is_ok() {
+                log::warn!("Sync session '{}' is already used by another shell", self.session);
+                return Err(anyhow!("Session '{}' is already used by another shell", self.session));
+            }
+            fs::remove_file(&self.socket_path)?;
+        }
+        let socket = UnixDatagram::bind(&self.socket_path)?;
+        socket.set_read_timeout(Some(Duration::from_secs(1)))?;
+        let inode = fs::metadata(&self.socket_path)?.ino();
+        log::info!("Sync listening: {:?}", &self.socket_path);
+
+        let mut buf = vec![0u8; 4096];
+        let result = loop {
+            // シェルが終了していたら抜ける
+            if let Some(pid) = signal_pid {
+                if unsafe { libc::kill(pid, 0) } != 0 {
+                    break Ok(());
+                }
+            }
+            // ソケットが消されたり作り直されたりしたら抜ける (他のソケットは消さない)
+            match fs::metadata(&self.socket_path) {
+                Ok(m) if m.ino() == inode => {}
+                _ => return Ok(()),
+            }
+
+            let size = match socket.recv(&mut buf) {
+                Ok(size) => size,
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => continue,
+                Err(e) => break Err(e.into()),
+            };
+            let path = Path::new(std::ffi::OsStr::from_bytes(&buf[..size]));
+            log::info!("Sync received: {:?}", path);
+
+            // 出力先が閉じられていたら終了
+            let mut out = stdout();
+            if writeln!(out, "{}", path.to_string_lossy()).and_then(|_| out.flush()).is_err() {
+                break Ok(());
+            }
+            if let Some(pid) = signal_pid {
+                unsafe { libc::kill(pid, libc::SIGUSR1) };
+            }
+        };
+
+        let _ = fs::remove_file(&self.socket_path);
+        result
+    }
+
+    #[cfg(not(unix))]
+    pub fn run(&self, _signal_pid: Option<i32>) -> Result<()> {
+        Err(anyhow!("Sync is only supported on Unix"))
+    }
+}
@@ -0,0 +1,280 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::env;
+
+use crate::color as COLOR;
+use crate::config::{ColorSpec, ThemeConfig, ThemePreset};
+use crate::git::GitState;
+use crate::icon::ICON_NAMES;
+
+// 色をつけるもの (icon.<種類> はファイルのアイコン)
+const KEYS: &[&str] = &[
+    "cursor",           // カーソル上の名前
+    "secondary_cursor", // コピー/移動先を選ぶカーソル
+    "marked",           // マークした名前
+    "search_match",     // 検索で一致した文字 (下線つき)
+    "folder",           // フォルダのアイコン
+    "file",             // 色を決めていないファイルのアイコン
+    "message",          // コンソールメッセージの文字
+    "info",             // コンソールメッセージの背景
+    "error",
+    "notify",
+    "git.ignored",
+    "git.untracked",
+    "git.added",
+    "git.modified",
+    "git.conflicted",
+];
+
+// 背景色として使うもの
+const BACKGROUND_KEYS: &[&str] = &["info", "error", "notify"];
+
+const DARK: &[(&str, &str)] = &[
+    ("cursor", "blue"),
+    ("secondary_cursor", "green"),
+    ("marked", "magenta"),
+    ("search_match", "yellow"),
+    ("folder", "yellow"),
+    ("file", "default"),
+    ("message", "default"),
+    ("info", "blue"),
+    ("error", "red"),
+    ("notify", "green"),
+    ("git.ignored", "bright_black"),
+    ("git.untracked", "cyan"),
+    ("git.added", "green"),
+    ("git.modified", "yellow"),
+    ("git.conflicted", "red"),
+    ("icon.rust", "208"),
+    ("icon.python", "75"),
+    ("icon.go", "81"),
+    ("icon.javascript", "221"),
+    ("icon.typescript", "75"),
+    ("icon.html", "202"),
+    ("icon.css", "75"),
+    ("icon.markdown", "111"),
+    ("icon.git", "202"),
+    ("icon.docker", "39"),
+    ("icon.image", "140"),
+    ("icon.pdf", "160"),
+];
+
+// 白い背景でも読めるように暗めの色にする
+const LIGHT: &[(&str, &str)] = &[
+    ("cursor", "26"),
+    ("secondary_cursor", "28"),
+    ("marked", "127"),
+    ("search_match", "130"),
+    ("folder", "136"),
+    ("file", "default"),
+    ("message", "bright_white"),
+    ("info", "25"),
+    ("error", "124"),
+    ("notify", "28"),
+    ("git.ignored", "245"),
+    ("git.untracked", "30"),
+    ("git.added", "28"),
+    ("git.modified", "130"),
+    ("git.conflicted", "124"),
+    ("icon.rust", "166"),
+    ("icon.python", "25"),
+    ("icon.go", "31"),
+    ("icon.javascript", "136"),
+    ("icon.typescript", "25"),
+    ("icon.html", "166"),
+    ("icon.css", "25"),
+    ("icon.markdown", "61"),
+    ("icon.git", "166"),
+    ("icon.docker", "31"),
+    ("icon.image", "96"),
+    ("icon.pdf", "124"),
+];
+
+// NO_COLORのときは色を使わず反転/下線/太字で区別する
+const NO_COLOR: &[(&str, &str)] = &[
+    ("cursor", "\x1b[7m"),
+    ("secondary_cursor", COLOR::UNDERLINE),
+    ("marked", COLOR::BOLD),
+    ("message", "\x1b[7m"),
+];
+
+// ----------------------------------------------------------------
+// 表示に使う色 (エスケープシーケンス)
+// ----------------------------------------------------------------
+#[derive(Debug, Clone)]
+pub struct Theme {
+    colors: HashMap<String, String>,
+}
+
+impl Theme {
+    // 色の書き方や名前に誤りがあればエラー
+    pub fn new(config: &ThemeConfig) -> Result<Theme> {
+        let mut specs: HashMap<String, ColorSpec> = HashMap::new();
+        let preset = match config.preset {
+            ThemePreset::Dark => DARK,
+            ThemePreset::Light => LIGHT,
+        };
+        for (key, name) in preset.iter() {
+            specs.insert(key.to_string(), ColorSpec::Name(name.to_string()));
+        }
+        for (key, spec) in config.colors.iter() {
+            if !is_valid_key(key) {
+                return Err(anyhow!("Unknown theme color: {}", key))
+            }
+            specs.insert(key.clone(), spec.clone());
+        }
+
+        let mut colors: HashMap<String, String> = HashMap::new();
+        for (key, spec) in specs.iter() {
+            let background = BACKGROUND_KEYS.contains(&key.as_str());
+            let escape = to_escape(spec, background).map_err(|e| anyhow!("theme.colors.{}: {}", key, e))?;
+            colors.insert(key.clone(), escape);
+        }
+
+        // 設定が正しいかは確かめた上で色を消す
+        if env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+            colors = NO_COLOR.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        }
+        Ok(Theme { colors })
+    }
+
+    fn get(&self, key: &str) -> &str {
+        self.colors.get(key).map(|s| s.as_str()).unwrap_or("")
+    }
+
+    pub fn cursor(&self) -> &str {
+        self.get("cursor")
+    }
+    pub fn secondary_cursor(&self) -> &str {
+        self.get("secondary_cursor")
+    }
+    pub fn marked(&self) -> &str {
+        self.get("marked")
+    }
+    pub fn search_match(&self) -> &str {
+        self.get("search_match")
+    }
+    pub fn folder(&self) -> &str {
+        self.get("folder")
+    }
+    // アイコンの種類ごとの色 (決めていなければfileの色)
+    pub fn icon(&self, name: &str) -> &str {
+        match self.colors.get(&format!("icon.{}", name)) {
+            Some(color) => color,
+            None => self.get("file"),
+        }
+    }
+    // コンソールメッセージの (文字色, 背景色)
+    pub fn message(&self, key: &str) -> String {
+        format!("{}{}", self.get("message"), self.get(key))
+    }
+    pub fn git(&self, state: GitState) -> &str {
+        self.get(match state {
+            GitState::Ignored => "git.ignored",
+            GitState::Untracked => "git.untracked",
+            GitState::Added => "git.added",
+            GitState::Modified => "git.modified",
+            GitState::Conflicted => "git.conflicted",
+        })
+    }
+}
+
+fn is_valid_key(key: &str) -> bool {
+    match key.strip_prefix("icon.") {
+        Some(name) => ICON_NAMES.contains(&name),
+        None => KEYS.contains(&key),
+    }
+}
+
+// 色の書き方をエスケープシーケンスにする ("default" は端末の色のまま)
+fn to_escape(spec: &ColorSpec, background: bool) -> Result<String> {
+    let layer = if background { 48 } else { 38 };
+    let name = match spec {
+        ColorSpec::Index(n) => return Ok(format!("\x1b[{};5;{}m", layer, n)),
+        ColorSpec::Name(name) => name.trim().to_lowercase(),
+    };
+
+    if name == "default" {
+        return Ok(String::new())
+    }
+    if let Ok(n) = name.parse::<u8>() {
+        return Ok(format!("\x1b[{};5;{}m", layer, n))
+    }
+    if let Some(hex) = name.strip_prefix('#') {
+        // from_str_radixは先頭の "+" も受け付けるので文字を確かめる
+        let is_hex = hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit());
+        let rgb = u32::from_str_radix(hex, 16).ok().filter(|_| is_hex);
+        return match rgb {
+            Some(rgb) => Ok(format!("\x1b[{};2;{};{};{}m", layer, rgb >> 16, (rgb >> 8) & 0xff, rgb & 0xff)),
+            None => Err(anyhow!("invalid color {:?} (expected #rrggbb)", name)),
+        }
+    }
+
+    let (bright, base) = match name.strip_prefix("bright_") {
+        Some(base) => (true, base),
+        None => (false, name.as_str()),
+    };
+    let code = match base {
+        "black" => 0,
+        "red" => 1,
+        "green" => 2,
+        "yellow" => 3,
+        "blue" => 4,
+        "magenta" => 5,
+        "cyan" => 6,
+        "white" => 7,
+        _ => return Err(anyhow!("unknown color {:?}", name)),
+    };
+    let offset = match (background, bright) {
+        (false, false) => 30,
+        (false, true) => 90,
+        (true, false) => 40,
+        (true, true) => 100,
+    };
+    Ok(format!("\x1b[{}m", offset + code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn escape(yaml: &str, background: bool) -> Result<String> {
+        to_escape(&serde_yaml::from_str::<ColorSpec>(yaml).unwrap(), background)
+    }
+
+    #[test]
+    fn named_colors() {
+        assert_eq!(escape("red", false).unwrap(), "\x1b[31m");
+        assert_eq!(escape("red", true).unwrap(), "\x1b[41m");
+        assert_eq!(escape("bright_blue", false).unwrap(), "\x1b[94m");
+        assert_eq!(escape("bright_white", true).unwrap(), "\x1b[107m");
+        assert_eq!(escape("' Cyan '", false).unwrap(), "\x1b[36m");
+        assert_eq!(escape("default", true).unwrap(), "");
+    }
+
+    #[test]
+    fn hex_colors() {
+        assert_eq!(escape("'#ff8000'", false).unwrap(), "\x1b[38;2;255;128;0m");
+        assert_eq!(escape("'#00FF7f'", true).unwrap(), "\x1b[48;2;0;255;127m");
+    }
+
+    #[test]
+    fn indexed_colors() {
+        assert_eq!(escape("208", false).unwrap(), "\x1b[38;5;208m");
+        assert_eq!(escape("0", true).unwrap(), "\x1b[48;5;0m");
+        // 文字列で書いた番号も使える
+        assert_eq!(escape("'255'", false).unwrap(), "\x1b[38;5;255m");
+    }
+
+    #[test]
+    fn invalid_colors() {
+        assert!(escape("purple", false).is_err());
+        assert!(escape("bright_default", false).is_err());
+        assert!(escape("'#fff'", false).is_err());
+        assert!(escape("'#ff80001'", false).is_err());
+        assert!(escape("'#gg0000'", false).is_err());
+        assert!(escape("'#+12345'", false).is_err());
+        assert!(escape("'256'", false).is_err());
+        assert!(serde_yaml::from_str::<ColorSpec>("256").is_err());
+    }
+}
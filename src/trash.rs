@@ -0,0 +1,345 @@
+use anyhow::{anyhow, Result};
+use chrono::{Local, NaiveDateTime};
+use std::ffi::OsString;
+use std::fs;
+use std::io::Write;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+
+use crate::utils::file::{move_path, remove_path};
+use crate::utils::path::get_application_root;
+
+// ゴミ箱に入っているもの1つ分
+#[derive(Debug, Clone)]
+pub struct TrashItem {
+    pub name: String, // files/以下での名前
+    pub original_path: PathBuf,
+    pub deletion_date: String,
+}
+
+// ----------------------------------------------------------------
+// FreeDesktop形式のゴミ箱 (files/ と info/*.trashinfo)
+// Linuxでは ~/.local/share/Trash，それ以外では ~/.sidebar/trash を使う
+// ホームと別のファイルシステムのものはそのマウントポイントのゴミ箱
+// ($topdir/.Trash/$uid か $topdir/.Trash-$uid) へ入れてコピーを避ける
+// ----------------------------------------------------------------
+pub struct Trash {
+    root: PathBuf,
+    topdir: Option<PathBuf>, // マウントポイントのゴミ箱のとき (infoのPathはここからの相対パス)
+}
+
+impl Trash {
+    // ホームのゴミ箱
+    pub fn new() -> Result<Trash> {
+        let root = match Self::freedesktop_root() {
+            Some(root) => root,
+            None => get_application_root()?.join("trash"),
+        };
+        fs::create_dir_all(root.join("files"))?;
+        fs::create_dir_all(root.join("info"))?;
+        Ok(Trash { root, topdir: None })
+    }
+
+    #[cfg(target_os = "linux")]
+    fn freedesktop_root() -> Option<PathBuf> {
+        let data_home = match std::env::var_os("XDG_DATA_HOME") {
+            Some(p) if !p.is_empty() => PathBuf::from(p),
+            _ => dir::home_dir()?.join(".local").join("share"),
+        };
+        let root = data_home.join("Trash");
+        match fs::create_dir_all(&root) {
+            Ok(_) => Some(root),
+            Err(_) => None,
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn freedesktop_root() -> Option<PathBuf> {
+        None
+    }
+
+    // pathを入れる (戻す) ゴミ箱
+    // マウントポイントのゴミ箱を作れなければエラーにする
+    #[cfg(target_os = "linux")]
+    pub fn for_path(path: &Path) -> Result<Trash> {
+        let home = Self::new()?;
+        let device = match device_of(path) {
+            Some(device) => device,
+            None => return Ok(home),
+        };
+        if device_of(&home.root) == Some(device) {
+            return Ok(home);
+        }
+
+        let topdir = top_directory(path, device);
+        let uid = unsafe { libc::getuid() };
+        let root = match Self::shared_root(&topdir, uid) {
+            Some(root) => root,
+            None => topdir.join(format!(".Trash-{}", uid)),
+        };
+        Self::create_private_dir(&root)
+            .and_then(|_| Self::create_private_dir(&root.join("files")))
+            .and_then(|_| Self::create_private_dir(&root.join("info")))
+            .map_err(|e| anyhow!("Cannot use the trash in {:?}: {}", topdir, e))?;
+        Ok(Trash { root, topdir: Some(topdir) })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn for_path(_path: &Path) -> Result<Trash> {
+        Self::new()
+    }
+
+    // 管理者が用意した $topdir/.Trash (スティッキービットがありシンボリックリンクでないもの)
+    #[cfg(target_os = "linux")]
+    fn shared_root(topdir: &Path, uid: u32) -> Option<PathBuf> {
+        use std::os::unix::fs::PermissionsExt;
+        let shared = topdir.join(".Trash");
+        let metadata = shared.symlink_metadata().ok()?;
+        if !metadata.is_dir() || metadata.permissions().mode() & 0o1000 == 0 {
+            return None;
+        }
+        Some(shared.join(uid.to_string()))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn create_private_dir(path: &Path) -> std::io::Result<()> {
+        use std::os::unix::fs::DirBuilderExt;
+        match fs::DirBuilder::new().mode(0o700).create(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::AlreadyExists => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    // 表示するゴミ箱 (ホームと，rootが別のファイルシステムならそのゴミ箱)
+    pub fn for_listing(root: &Path) -> Result<Vec<Trash>> {
+        let mut trashes = vec![Self::new()?];
+        if let Ok(trash) = Self::for_path(root) {
+            if trash.root != trashes[0].root {
+                trashes.push(trash);
+            }
+        }
+        Ok(trashes)
+    }
+
+    fn files_dir(&self) -> PathBuf {
+        self.root.join("files")
+    }
+
+    fn info_path(&self, name: &str) -> PathBuf {
+        self.root.join("info").join(format!("{}.trashinfo", name))
+    }
+
+    // ゴミ箱へ移動 --------------------------------------------------
+    pub fn put(&self, path: &Path) -> Result<TrashItem> {
+        // UTF-8でない名前はエスケープして元の名前と区別できるようにする
+        let base_name = match path.file_name() {
+            Some(name) => match name.to_str() {
+                Some(name) => name.to_string(),
+                None => encode_path(Path::new(name)),
+            },
+            None => return Err(anyhow!("Invalid path")),
+        };
+        let deletion_date = Local::now().format("%Y-%m-%dT%H:%M:%S").to_string();
+
+        // 同じ名前があれば番号をつける (infoファイルを先に排他的に作って名前を確保する)
+        let mut name = base_name.clone();
+        let mut count = 1;
+        let mut info_file = loop {
+            let candidate = fs::OpenOptions::new().write(true).create_new(true).open(self.info_path(&name));
+            match candidate {
+                Ok(file) if !self.files_dir().join(&name).exists() => break file,
+                Ok(_) => { let _ = fs::remove_file(self.info_path(&name)); }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e.into()),
+            }
+            count += 1;
+            name = format!("{}.{}", base_name, count);
+        };
+
+        write!(
+            info_file,
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            encode_path(self.info_value(path)),
+            deletion_date
+        )?;
+
+        if let Err(e) = move_path(path, &self.files_dir().join(&name)) {
+            let _ = fs::remove_file(self.info_path(&name));
+            return Err(e);
+        }
+        log::info!("Trash {:?} as {:?}", path, &name);
+
+        Ok(TrashItem {
+            name,
+            original_path: path.to_path_buf(),
+            deletion_date,
+        })
+    }
+
+    // 中身の一覧 (新しい順) -------------------------------------------
+    pub fn list(&self) -> Result<Vec<TrashItem>> {
+        let mut items: Vec<TrashItem> = Vec::new();
+        for entry in fs::read_dir(self.root.join("info"))? {
+            let path = entry?.path();
+            let name = match path.file_name().and_then(|n| n.to_str()).and_then(|n| n.strip_suffix(".trashinfo")) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            // 壊れたinfoファイルは無視
+            if let Ok(item) = Self::parse_info(&name, &fs::read_to_string(&path)?, self.topdir.as_deref()) {
+                if self.files_dir().join(&name).symlink_metadata().is_ok() {
+                    items.push(item);
+                }
+            }
+        }
+        items.sort_by(|a, b| b.deletion_date.cmp(&a.deletion_date));
+        Ok(items)
+    }
+
+    // マウントポイントのゴミ箱ではtopdirからの相対パスで書く
+    fn info_value<'a>(&self, path: &'a Path) -> &'a Path {
+        match self.topdir {
+            Some(ref topdir) => path.strip_prefix(topdir).unwrap_or(path),
+            None => path,
+        }
+    }
+
+    fn parse_info(name: &str, content: &str, topdir: Option<&Path>) -> Result<TrashItem> {
+        let mut original_path: Option<PathBuf> = None;
+        let mut deletion_date = String::new();
+        for line in content.lines() {
+            if let Some(value) = line.strip_prefix("Path=") {
+                let path = decode_path(value);
+                original_path = Some(match topdir {
+                    Some(topdir) if path.is_relative() => topdir.join(path),
+                    _ => path,
+                });
+            } else if let Some(value) = line.strip_prefix("DeletionDate=") {
+                if NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S").is_ok() {
+                    deletion_date = value.to_string();
+                }
+            }
+        }
+        match original_path {
+            Some(p) => Ok(TrashItem { name: name.to_string(), original_path: p, deletion_date }),
+            None => Err(anyhow!("Invalid trashinfo: {}", name)),
+        }
+    }
+
+    // 元の場所へ戻す (上書きするかは呼び出し側で確認しておく) ------------------
+    pub fn restore(&self, item: &TrashItem) -> Result<()> {
+        if let Some(parent) = item.original_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        move_path(&self.files_dir().join(&item.name), &item.original_path)?;
+        fs::remove_file(self.info_path(&item.name))?;
+        log::info!("Restore {:?} from trash", &item.original_path);
+        Ok(())
+    }
+
+    // ゴミ箱から完全に削除 ---------------------------------------------
+    pub fn purge(&self, item: &TrashItem) -> Result<()> {
+        remove_path(&self.files_dir().join(&item.name))?;
+        fs::remove_file(self.info_path(&item.name))?;
+        log::info!("Purge {:?} from trash", &item.original_path);
+        Ok(())
+    }
+}
+
+// 削除するパスのファイルシステム (無ければ最も近い親フォルダのもの)
+#[cfg(target_os = "linux")]
+fn device_of(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    path.ancestors().find_map(|p| p.symlink_metadata().ok()).map(|m| m.dev())
+}
+
+// 同じファイルシステムのうち最も上のフォルダ (マウントポイント)
+#[cfg(target_os = "linux")]
+fn top_directory(path: &Path, device: u64) -> PathBuf {
+    let mut topdir = path.to_path_buf();
+    for ancestor in path.ancestors().skip(1) {
+        match device_of(ancestor) {
+            Some(d) if d == device => topdir = ancestor.to_path_buf(),
+            _ => break,
+        }
+    }
+    topdir
+}
+
+// trashinfoのPathはURLと同じ形式でエスケープする (UTF-8でないパスもバイト列のまま)
+fn encode_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for byte in path.as_os_str().as_bytes() {
+        match *byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => encoded.push(*byte as char),
+            b => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+
+fn decode_path(value: &str) -> PathBuf {
+    let bytes = value.as_bytes();
+    let mut decoded: Vec<u8> = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+            if let Ok(b) = u8::from_str_radix(hex, 16) {
+                decoded.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    PathBuf::from(OsString::from_vec(decoded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_and_decode_round_trip() {
+        let path = Path::new("/tmp/a b/100%/日本語.txt");
+        assert_eq!(encode_path(path), "/tmp/a%20b/100%25/%E6%97%A5%E6%9C%AC%E8%AA%9E.txt");
+        assert_eq!(decode_path(&encode_path(path)), path);
+    }
+
+    #[test]
+    fn non_utf8_paths_keep_their_bytes() {
+        let path = PathBuf::from(OsString::from_vec(b"/tmp/caf\xe9.txt".to_vec()));
+        assert_eq!(encode_path(&path), "/tmp/caf%E9.txt");
+        assert_eq!(decode_path("/tmp/caf%E9.txt"), path);
+    }
+
+    #[test]
+    fn broken_escapes_are_kept_as_is() {
+        assert_eq!(decode_path("/tmp/%zz/50%"), PathBuf::from("/tmp/%zz/50%"));
+    }
+
+    #[test]
+    fn parse_info_reads_path_and_date() {
+        let content = "[Trash Info]\nPath=/home/me/a%20b.txt\nDeletionDate=2024-05-01T12:34:56\n";
+        let item = Trash::parse_info("a b.txt", content, None).unwrap();
+        assert_eq!(item.name, "a b.txt");
+        assert_eq!(item.original_path, PathBuf::from("/home/me/a b.txt"));
+        assert_eq!(item.deletion_date, "2024-05-01T12:34:56");
+    }
+
+    #[test]
+    fn parse_info_joins_relative_paths_to_topdir() {
+        let content = "[Trash Info]\nPath=docs/a.txt\nDeletionDate=2024-05-01T12:34:56\n";
+        let item = Trash::parse_info("a.txt", content, Some(Path::new("/mnt/usb"))).unwrap();
+        assert_eq!(item.original_path, PathBuf::from("/mnt/usb/docs/a.txt"));
+    }
+
+    #[test]
+    fn parse_info_ignores_invalid_date_and_rejects_missing_path() {
+        let item = Trash::parse_info("a", "[Trash Info]\nPath=/a\nDeletionDate=yesterday\n", None).unwrap();
+        assert_eq!(item.deletion_date, "");
+        assert!(Trash::parse_info("a", "[Trash Info]\nDeletionDate=2024-05-01T12:34:56\n", None).is_err());
+    }
+}
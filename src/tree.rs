@@ -0,0 +1,118 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::icon;
+use crate::node::NodeType;
+use crate::node_map::NodeMap;
+use crate::theme::Theme;
+use crate::width::strip_ansi;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TreeFormat {
+    Text,
+    Json,
+    Yaml,
+}
+
+// JSON/YAMLで出力する1ノード分 (展開していないフォルダはchildrenを持たない)
+#[derive(Serialize, Debug)]
+struct TreeEntry {
+    name: String,
+    path: String,
+    #[serde(rename = "type")]
+    node_type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    children: Option<Vec<TreeEntry>>,
+}
+
+// ----------------------------------------------------------------
+// 画面を使わずにツリーを出力する (sidebar tree)
+// TUIと同じようにconfig.ignoreなどで無視し，同じ順番で並べる
+// depthはルートから何階層下まで展開するか
+// ----------------------------------------------------------------
+pub fn dump(root: PathBuf, config: Config, depth: usize, format: TreeFormat, show_hidden: bool) -> Result<String> {
+    let theme = Theme::new(&config.theme)?;
+    let mut node_map = NodeMap::new(root, Arc::new(config));
+    if show_hidden {
+        node_map.toggle_hidden();
+    }
+
+    // 浅いほうから1階層ずつ開く
+    for level in 0..depth {
+        for id in node_map.serialize()? {
+            if node_map.get_rank(&id)? == level
+                && node_map.get_node_type(&id)? == NodeType::Folder
+                && !node_map.get_is_open(&id)?
+            {
+                node_map.open_and_close_node(&id)?;
+            }
+        }
+    }
+
+    let ids = node_map.serialize()?;
+    match format {
+        TreeFormat::Text => to_text(&node_map, &ids, &theme),
+        TreeFormat::Json => {
+            let mut pos = 0;
+            Ok(serde_json::to_string_pretty(&to_entry(&node_map, &ids, &mut pos)?)? + "\n")
+        }
+        TreeFormat::Yaml => {
+            let mut pos = 0;
+            Ok(serde_yaml::to_string(&to_entry(&node_map, &ids, &mut pos)?)?)
+        }
+    }
+}
+
+// 画面と同じ字下げとアイコン (端末以外に出力するときは色をつけない)
+fn to_text(node_map: &NodeMap, ids: &[Uuid], theme: &Theme) -> Result<String> {
+    let nerd_font = node_map.get_config().nerd_font;
+    let colored = std::io::stdout().is_terminal();
+
+    let mut out = String::new();
+    for id in ids.iter() {
+        let name = node_map.get_name(id)?;
+        let icon = match node_map.get_node_type(id)? {
+            NodeType::Folder => icon::get_folder_icon(node_map.get_is_open(id)?, nerd_font, theme),
+            NodeType::File => icon::get_file_icon(&name, nerd_font, theme),
+            NodeType::Unknown => String::from("?"),
+        };
+        let icon = if colored { icon } else { strip_ansi(&icon) };
+        out.push_str(&format!("{}{} {}\n", "  ".repeat(node_map.get_rank(id)?), icon, name));
+    }
+    Ok(out)
+}
+
+// serializeの並び (字下げの深さ順) から入れ子の構造に戻す
+fn to_entry(node_map: &NodeMap, ids: &[Uuid], pos: &mut usize) -> Result<TreeEntry> {
+    let id = ids[*pos];
+    *pos += 1;
+    let rank = node_map.get_rank(&id)?;
+    let node_type = node_map.get_node_type(&id)?;
+
+    let children = if node_type == NodeType::Folder && node_map.get_is_open(&id)? {
+        let mut children: Vec<TreeEntry> = Vec::new();
+        while *pos < ids.len() && node_map.get_rank(&ids[*pos])? > rank {
+            children.push(to_entry(node_map, ids, pos)?);
+        }
+        Some(children)
+    } else {
+        None
+    };
+
+    Ok(TreeEntry {
+        name: node_map.get_name(&id)?,
+        path: node_map.get_path(&id)?.to_string_lossy().into_owned(),
+        node_type: match node_type {
+            NodeType::Folder => "folder",
+            NodeType::File => "file",
+            NodeType::Unknown => "unknown",
+        },
+        children,
+    })
+}
@@ -54,3 +54,119 @@ pub mod path {
         return Err(anyhow!("Impossible to get file type!"));
     }
 }
+
+pub mod file {
+    use anyhow::Result;
+    use std::fs;
+    use std::path::Path;
+
+    // ファイル操作 (1つ分) -----------------------------------------------------
+    // 上書きするかどうかは呼び出し側で確認しておく
+    pub fn copy_path(from_path: &Path, to_path: &Path) -> Result<()> {
+        if from_path.is_dir() {
+            let mut option = fs_extra::dir::CopyOptions::new();
+            option.overwrite = true;
+            option.copy_inside = true;
+            fs_extra::dir::copy(from_path, to_path, &option)?;
+        } else {
+            let mut option = fs_extra::file::CopyOptions::new();
+            option.overwrite = true;
+            fs_extra::file::copy(from_path, to_path, &option)?;
+        }
+        Ok(())
+    }
+
+    // 同じファイルシステム内ならrenameで済ませ，できなければコピーして削除
+    pub fn move_path(from_path: &Path, to_path: &Path) -> Result<()> {
+        if fs::rename(from_path, to_path).is_ok() {
+            return Ok(())
+        }
+        if from_path.is_dir() {
+            let mut option = fs_extra::dir::CopyOptions::new();
+            option.overwrite = true;
+            option.copy_inside = true;
+            fs_extra::dir::move_dir(from_path, to_path, &option)?;
+        } else {
+            let mut option = fs_extra::file::CopyOptions::new();
+            option.overwrite = true;
+            fs_extra::file::move_file(from_path, to_path, &option)?;
+        }
+        Ok(())
+    }
+
+    // シンボリックリンクはリンク先ではなくリンク自体を消す
+    pub fn remove_path(path: &Path) -> Result<()> {
+        if path.is_dir() && !path.is_symlink() {
+            fs::remove_dir_all(path)?;
+        } else {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(unix)] // Unix系システム（Linux、macOSなど）
+    pub fn create_symlink(source: &Path, dest: &Path) -> Result<()> {
+        use std::os::unix::fs::symlink;
+        symlink(source, dest)?;
+        Ok(())
+    }
+
+    #[cfg(windows)] // Windowsシステム用
+    pub fn create_symlink(source: &Path, dest: &Path) -> Result<()> {
+        use std::os::windows::fs::symlink_file;
+        symlink_file(source, dest)?;
+        Ok(())
+    }
+}
+
+pub mod format {
+    // バイト数を読みやすい単位に (1.5MBなど)
+    pub fn human_size(bytes: u64) -> String {
+        let units = ["B", "KB", "MB", "GB", "TB"];
+        let mut size = bytes as f64;
+        let mut unit = 0;
+        while size >= 1024.0 && unit < units.len() - 1 {
+            size /= 1024.0;
+            unit += 1;
+        }
+        if unit == 0 {
+            format!("{}{}", bytes, units[0])
+        } else {
+            format!("{:.1}{}", size, units[unit])
+        }
+    }
+}
+
+pub mod terminal {
+    use anyhow::Result;
+    use crossterm::{cursor, execute, style::Print, terminal::{
+        disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+        }
+    };
+    use std::io::stdout;
+
+    // マウスはクリックとホイールだけを受け取る (SGR形式)
+    // EnableMouseCaptureはポインタの移動も全て送ってくるので使わない
+    const ENABLE_MOUSE: &str = "\x1b[?1000h\x1b[?1006h";
+    const DISABLE_MOUSE: &str = "\x1b[?1006l\x1b[?1000l";
+
+    // TUIの画面に切り替える
+    pub fn enter(mouse: bool) -> Result<()> {
+        enable_raw_mode()?;
+        execute!(stdout(), EnterAlternateScreen, cursor::Hide)?;
+        if mouse {
+            execute!(stdout(), Print(ENABLE_MOUSE))?;
+        }
+        Ok(())
+    }
+
+    // 元の画面に戻す (終了するときや別のプログラムに端末を渡すとき)
+    pub fn leave(mouse: bool) -> Result<()> {
+        if mouse {
+            execute!(stdout(), Print(DISABLE_MOUSE))?;
+        }
+        execute!(stdout(), cursor::Show, LeaveAlternateScreen)?;
+        disable_raw_mode()?;
+        Ok(())
+    }
+}
@@ -3,17 +3,22 @@ use crate::icon;
 use crate::node::NodeType;
 use crate::node_map::NodeMap;
 use crate::config::Config;
+use crate::git::{GitMark, GitStatus};
+use crate::preview;
+use crate::screen::Screen;
+use crate::search;
+use crate::theme::Theme;
+use crate::width;
 
 use anyhow::Result;
-use std::io::{stdout, Write};
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 
 use uuid::Uuid;
 
-use crossterm::cursor::{MoveTo, MoveToNextLine, MoveDown};
-use crossterm::style::Print;
-use crossterm::terminal::{Clear, ClearType};
-use crossterm::{queue, terminal};
+use crossterm::terminal;
 
 #[derive(Debug, Clone)]
 pub enum ConsoleMessageStatus{
@@ -34,25 +39,50 @@ impl ConsoleMessage{
         }
     }
     fn get_num_lines(&self, terminal_width: usize) -> usize {
-        return self.message.len().div_ceil(terminal_width);
+        width::wrap(&self.message, terminal_width).len()
     }
 
 }
 
+// ツリーの代わりに表示する一覧 (ゴミ箱など)
+#[derive(Debug)]
+struct Panel {
+    title: String,
+    items: Vec<String>,
+    cursor: usize,
+    start: usize,
+}
 
+// 検索語と一致した行
+#[derive(Debug)]
+struct Search {
+    query: String,
+    hits: Vec<usize>,                     // 一致した行 (id_listの位置)
+    positions: HashMap<Uuid, Vec<usize>>, // 名前の中で一致した文字の位置
+}
 
 #[allow(dead_code)]
 pub struct Viewer {
     node_map: Arc<Mutex<NodeMap>>,
     id_list: Vec<Uuid>,
     console_message: Option<ConsoleMessage>,
+    status_line: Option<String>,
     display_start_idx: usize,
     display_end_idx: usize,
     cursor_idx: usize,
     secondoy_cursor_mode: bool,
+    marked_ids: Vec<Uuid>,
+    mark_anchor: Option<Uuid>,
+    panel: Option<Panel>,
+    search: Option<Search>,
+    preview_mode: bool,
+    preview_cache: Option<(PathBuf, Option<SystemTime>, Vec<String>)>, // (パス, 更新日時, プレビュー)
+    git_status: Option<GitStatus>, // ルートがgitのワークツリーの中にあるとき
     terminal_width: usize,
     terminal_height: usize,
     config: Arc<Config>,
+    screen: Screen, // 前回描画した内容
+    theme: Theme,
 }
 
 #[allow(dead_code)]
@@ -60,21 +90,35 @@ impl Viewer {
     // ----------------------------------------------------------------
     // コンストラクタ
     // ----------------------------------------------------------------
-    pub fn new(node_map: Arc<Mutex<NodeMap>>, config: Arc<Config>) -> Viewer {
+    pub fn new(node_map: Arc<Mutex<NodeMap>>, config: Arc<Config>, theme: Theme) -> Viewer {
         let (width, height) = terminal::size().unwrap();
         let id_list: Vec<Uuid> = Vec::new();
+        let git_status = {
+            let node_map = node_map.lock().unwrap();
+            node_map.get_path(&node_map.get_root_id()).ok().and_then(|root| GitStatus::load(&root))
+        };
 
         Viewer {
             node_map: node_map,
             id_list: id_list,
             console_message: None,
+            status_line: None,
             display_start_idx: 0,
             display_end_idx: 1,
             cursor_idx: 0,
             secondoy_cursor_mode: false,
+            marked_ids: Vec::new(),
+            mark_anchor: None,
+            panel: None,
+            search: None,
+            preview_mode: config.preview.enabled,
+            preview_cache: None,
+            git_status,
             terminal_width: width as usize,
             terminal_height: height as usize,
             config: config,
+            screen: Screen::new(),
+            theme,
         }
     }
 
@@ -93,11 +137,21 @@ impl Viewer {
         self.console_message = None
     }
 
+    // ステータス行 (実行中のジョブなど) を保存し，変わったときはtrueを返す
+    pub fn set_status_line(&mut self, status_line: Option<String>) -> bool {
+        if self.status_line == status_line {
+            return false
+        }
+        self.status_line = status_line;
+        true
+    }
+
     // ターミナルのサイズが変更されたときに呼び出される
     pub fn resize(&mut self) -> Result<()>{
         let (width, height) = terminal::size()?;
         self.terminal_width = width as usize;
         self.terminal_height = height as usize;
+        self.screen.invalidate();
         Ok(())
     }
 
@@ -108,6 +162,260 @@ impl Viewer {
         self.secondoy_cursor_mode = false;
     }
 
+    // パネル ---------------------------------------------------------
+    // 同じタイトルで開き直したときはカーソル位置を保つ
+    pub fn open_panel(&mut self, title: String, items: Vec<String>) {
+        let cursor = match self.panel {
+            Some(ref panel) if panel.title == title => panel.cursor.min(items.len().saturating_sub(1)),
+            _ => 0,
+        };
+        self.panel = Some(Panel { title, items, cursor, start: 0 });
+    }
+    pub fn close_panel(&mut self) {
+        self.panel = None;
+    }
+    pub fn panel_cursor_up(&mut self) {
+        if let Some(ref mut panel) = self.panel {
+            panel.cursor = panel.cursor.saturating_sub(1);
+        }
+    }
+    pub fn panel_cursor_down(&mut self) {
+        if let Some(ref mut panel) = self.panel {
+            if panel.cursor + 1 < panel.items.len() {
+                panel.cursor += 1;
+            }
+        }
+    }
+    // 項目が無いときはNone
+    pub fn get_panel_cursor(&self) -> Option<usize> {
+        match self.panel {
+            Some(ref panel) if !panel.items.is_empty() => Some(panel.cursor),
+            _ => None,
+        }
+    }
+
+    // プレビュー -------------------------------------------------------
+    pub fn toggle_preview(&mut self) {
+        self.preview_mode = !self.preview_mode;
+        self.preview_cache = None;
+    }
+
+    // ルートが変わったときに表示の状態を初期化する ----------------------
+    pub fn reset_for_new_root(&mut self) -> Result<()> {
+        self.clear_marks();
+        self.search = None;
+        self.preview_cache = None;
+        self.cursor_idx = 0;
+        self.display_start_idx = 0;
+        self.git_status = {
+            let node_map = self.node_map.lock().unwrap();
+            node_map.get_path(&node_map.get_root_id()).ok().and_then(|root| GitStatus::load(&root))
+        };
+        self.sync()
+    }
+
+    // gitの状態を別のスレッドで取り直す ---------------------------------
+    pub fn start_git_refresh(&mut self) {
+        if let Some(ref mut git_status) = self.git_status {
+            git_status.start_refresh();
+        }
+    }
+
+    // 取り直したgitの状態を反映したらtrue (再描画が必要)
+    pub fn poll_git_status(&mut self) -> bool {
+        match self.git_status {
+            Some(ref mut git_status) => git_status.poll_refresh(),
+            None => false,
+        }
+    }
+
+    // 名前の後ろにつける印 (変更を含むフォルダは•)
+    fn get_git_marker(&self, path: &std::path::Path) -> Option<String> {
+        let mark = self.git_status.as_ref()?.get_mark(path)?;
+        let (c, state) = match mark {
+            GitMark::File(state) => (state.marker(), state),
+            GitMark::Dirty(state) => ('•', state),
+        };
+        Some(format!(" {}{}", self.theme.git(state), c))
+    }
+
+    // 検索 -----------------------------------------------------------
+    // Noneか空文字のときは検索を終了する
+    pub fn set_search(&mut self, query: Option<String>) -> Result<()> {
+        self.search = match query {
+            Some(query) if !query.is_empty() => Some(Search { query, hits: Vec::new(), positions: HashMap::new() }),
+            _ => None,
+        };
+        let node_map = self.node_map.clone();
+        let node_map = node_map.lock().unwrap();
+        self.update_search(&node_map)
+    }
+
+    // 表示されている行から一致するものを探し直す
+    fn update_search(&mut self, node_map: &NodeMap) -> Result<()> {
+        let search = match self.search {
+            Some(ref mut search) => search,
+            None => return Ok(()),
+        };
+        let root_path = node_map.get_path(&node_map.get_root_id())?;
+        search.hits.clear();
+        search.positions.clear();
+        for (i, id) in self.id_list.iter().enumerate() {
+            let name = node_map.get_name(id)?;
+            let path = node_map.get_path(id)?;
+            let relative = path.strip_prefix(&root_path).unwrap_or(&path);
+            let (text, offset) = search::target_text(&search.query, &name, relative);
+            if let Some(m) = search::fuzzy_match(&search.query, &text) {
+                let positions = m.positions.iter().filter(|p| **p >= offset).map(|p| p - offset).collect();
+                search.hits.push(i);
+                search.positions.insert(*id, positions);
+            }
+        }
+        Ok(())
+    }
+
+    // (カーソルが何件目の一致か, 一致した件数)
+    pub fn get_search_count(&self) -> Option<(usize, usize)> {
+        let search = self.search.as_ref()?;
+        let current = search.hits.iter().position(|i| *i == self.cursor_idx).map_or(0, |p| p + 1);
+        Some((current, search.hits.len()))
+    }
+
+    // 次(前)の一致した行へカーソルを移動 (端まで行ったら反対側へ戻る)
+    // include_currentのときはカーソル上の行も含める
+    pub fn jump_to_hit(&mut self, forward: bool, include_current: bool) -> bool {
+        let hits = match self.search {
+            Some(ref search) if !search.hits.is_empty() => &search.hits,
+            _ => return false,
+        };
+        let cursor = self.cursor_idx;
+        let next = if forward {
+            hits.iter().find(|i| **i > cursor || (include_current && **i == cursor)).unwrap_or(&hits[0])
+        } else {
+            hits.iter().rev().find(|i| **i < cursor || (include_current && **i == cursor)).unwrap_or(&hits[hits.len() - 1])
+        };
+        self.cursor_idx = *next;
+        true
+    }
+
+    // カーソルを指定したノードへ (表示されていなければ何もしない)
+    pub fn set_cursor_id(&mut self, id: &Uuid) {
+        if let Some(idx) = self.id_list.iter().position(|i| i == id) {
+            self.cursor_idx = idx;
+        }
+    }
+    // 表示している先頭の行 (セッションの保存用)
+    pub fn get_scroll(&self) -> usize {
+        self.display_start_idx
+    }
+    pub fn set_scroll(&mut self, start: usize) {
+        self.display_start_idx = start.min(self.id_list.len().saturating_sub(1));
+    }
+    pub fn get_cursor_idx(&self) -> usize {
+        self.cursor_idx
+    }
+    pub fn set_cursor_idx(&mut self, idx: usize) {
+        self.cursor_idx = idx.min(self.id_list.len().saturating_sub(1));
+    }
+
+    // ホイールで表示範囲を動かす (カーソルは表示範囲の中に収める)
+    pub fn scroll(&mut self, lines: isize) {
+        let display_height = self.get_display_height();
+        let max_start = self.id_list.len().saturating_sub(display_height.saturating_sub(1));
+        let start = (self.display_start_idx as isize + lines).clamp(0, max_start as isize) as usize;
+        self.display_start_idx = start;
+
+        // update_display_sizeで表示範囲が戻されないように最後の行には置かない
+        let last = (start + display_height.saturating_sub(2)).min(self.id_list.len().saturating_sub(1));
+        self.cursor_idx = self.cursor_idx.clamp(start, last.max(start));
+    }
+
+    // 画面上の位置にあるノードの行番号と，そこがアイコン (と直後の空白) の上かどうか
+    pub fn get_node_at(&self, row: usize, column: usize) -> Option<(usize, bool)> {
+        if self.panel.is_some() || row >= self.get_display_height() || column >= self.get_layout().0 {
+            return None
+        }
+        let idx = self.display_start_idx + row;
+        let id = self.id_list.get(idx)?;
+        let rank = self.node_map.lock().unwrap().get_rank(id).ok()?;
+        let on_icon = column >= rank * 2 && column < rank * 2 + 2;
+        Some((idx, on_icon))
+    }
+
+    // 選択 (マーク) ---------------------------------------------------
+    pub fn get_marked_ids(&self) -> Vec<Uuid> {
+        self.marked_ids.clone()
+    }
+
+    // カーソル上のノードのマークを切り替えて次の行へ
+    pub fn toggle_mark(&mut self) {
+        let id = self.get_cursor_id();
+        if let Some(pos) = self.marked_ids.iter().position(|m| *m == id) {
+            self.marked_ids.remove(pos);
+        } else {
+            self.marked_ids.push(id);
+        }
+        self.mark_anchor = Some(id);
+        self.cursor_down();
+    }
+
+    // 最後にマークを切り替えた行からカーソルまでをマーク
+    pub fn mark_range(&mut self) {
+        let anchor_idx = self.mark_anchor
+            .and_then(|anchor| self.id_list.iter().position(|id| *id == anchor))
+            .unwrap_or(self.cursor_idx);
+        let (start, end) = if anchor_idx <= self.cursor_idx {
+            (anchor_idx, self.cursor_idx)
+        } else {
+            (self.cursor_idx, anchor_idx)
+        };
+        for i in start..=end {
+            let id = self.id_list[i];
+            if !self.marked_ids.contains(&id) {
+                self.marked_ids.push(id);
+            }
+        }
+        self.mark_anchor = Some(self.get_cursor_id());
+    }
+
+    // カーソルと同じフォルダにある(表示されている)ノードを全てマーク
+    pub fn mark_siblings(&mut self) -> Result<()> {
+        let node_map = self.node_map.lock().unwrap();
+        let rank = node_map.get_rank(&self.id_list[self.cursor_idx])?;
+
+        // ルートのときはルートのみ
+        if rank == 0 {
+            std::mem::drop(node_map);
+            if !self.marked_ids.contains(&self.id_list[0]) {
+                self.marked_ids.push(self.id_list[0]);
+            }
+            return Ok(());
+        }
+
+        // 親ノードの次の行から同じフォルダの最後の行までを探す
+        let mut start = self.cursor_idx;
+        while start > 0 && node_map.get_rank(&self.id_list[start - 1])? >= rank {
+            start -= 1;
+        }
+        let mut end = self.cursor_idx;
+        while end + 1 < self.id_list.len() && node_map.get_rank(&self.id_list[end + 1])? >= rank {
+            end += 1;
+        }
+
+        for i in start..=end {
+            let id = self.id_list[i];
+            if node_map.get_rank(&id)? == rank && !self.marked_ids.contains(&id) {
+                self.marked_ids.push(id);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn clear_marks(&mut self) {
+        self.marked_ids.clear();
+        self.mark_anchor = None;
+    }
+
     // カーソルを上に -----------------------
     pub fn cursor_up(&mut self) {
         // 更新------------------
@@ -184,39 +492,61 @@ impl Viewer {
 
     // node_mapと同期 -------------------------
     pub fn sync(&mut self) -> Result<()> {
-        let node_map = self.node_map.lock().unwrap();
+        let node_map = self.node_map.clone();
+        let node_map = node_map.lock().unwrap();
 
         // id_listの更新
         let id_list = node_map.serialize()?;
         self.id_list = id_list;
 
+        // 既に無くなったノードのマークを外す
+        self.marked_ids.retain(|id| node_map.contains(id));
+
         // カーソルを長さに合わせる
         if self.id_list.len() <= self.cursor_idx {
             self.cursor_idx = self.id_list.len() - 1;
         }
 
+        // 検索中なら一致する行を探し直す
+        self.update_search(&node_map)?;
+
         Ok(())
     }
 
     // 各行の出力を生成 ----------------------------------
-    fn format(&self, name: String, icon: String, rank: usize, color: &str) -> String {
-        let indent = String::from("  ").repeat(rank);
-        let prefix_length = icon.len() + indent.len();
+    // iは表示するノードの番号 (色，マーク，検索で一致した文字の位置はここで決める)
+    fn format(&self, i: usize, name: String, icon: String, rank: usize, width: usize) -> String {
+        let id = self.id_list[i];
+        let color = self.get_line_color(i);
+        let highlights: &[usize] = match self.search {
+            Some(ref search) => search.positions.get(&id).map(|p| p.as_slice()).unwrap_or_default(),
+            None => &[],
+        };
+        let mut indent = String::from("  ").repeat(rank);
+        // マークされている行はアイコンの直前に印をつける
+        if self.marked_ids.contains(&id) {
+            indent.pop();
+            indent.push('*');
+        }
+        // アイコンは色のエスケープシーケンスを除いた表示幅で数える (nerd fontは1桁，絵文字は2桁)
+        let prefix_width = indent.len() + width::str_width(&icon) + 1;
 
         // ターミナルのサイズに合わせる ------
-        let modified_name = if self.terminal_width < (prefix_length + name.len()) {
-            let available_length = self.terminal_width - 1;
+        let modified_name = width::truncate(&name, width.saturating_sub(prefix_width));
+
+        // 検索で一致した文字を強調 ------
+        let modified_name = if highlights.is_empty() {
+            modified_name
+        } else {
             let mut _name = String::new();
-            for c in name.chars() {
-                if prefix_length + _name.len() >= available_length {
-                    _name.push_str("…");
-                    break;
+            for (i, c) in modified_name.chars().enumerate() {
+                if highlights.contains(&i) {
+                    _name.push_str(&format!("{}{}{}{}{}", COLOR::UNDERLINE, self.theme.search_match(), c, COLOR::RESET, color));
+                } else {
+                    _name.push(c);
                 }
-                _name.push(c);
             }
             _name
-        } else {
-            name
         };
 
         // 結合 ----------------
@@ -224,22 +554,26 @@ impl Viewer {
         return line;
     }
 
-    // カーソル上のノード => 青
-    // セカンダリーカーソル上のノード => 緑
+    // カーソル上のノード => cursor (既定は青)
+    // セカンダリーカーソル上のノード => secondary_cursor (既定は緑)
+    // マークされたノード => marked (既定はマゼンタ)
     fn get_line_color(&self, i: usize) -> &str {
         if i != self.cursor_idx {
+            if self.marked_ids.contains(&self.id_list[i]) {
+                return self.theme.marked()
+            }
             return COLOR::RESET
         }
 
         if self.secondoy_cursor_mode{
-            return COLOR::front::GREEN
+            return self.theme.secondary_cursor()
         }
 
-        return COLOR::front::BLUE
+        self.theme.cursor()
     }
 
-    // 表示開始位置の更新
-    fn update_display_size(&mut self) {
+    // ツリーやパネルを表示できる行数
+    fn get_display_height(&self) -> usize {
         let mut display_height = self.terminal_height;
 
         // コンソールメッセージがある際はメッセージの行数分表示の範囲を狭める
@@ -248,6 +582,28 @@ impl Viewer {
             display_height -= num_lines;
 
         }
+        // ステータス行の分
+        if self.status_line.is_some() {
+            display_height -= 1;
+        }
+        display_height
+    }
+
+    // (ツリーの幅, プレビューの幅)
+    // プレビューを表示しないときや端末が狭いときは(端末の幅, 0)
+    fn get_layout(&self) -> (usize, usize) {
+        if !self.preview_mode || self.panel.is_some() || self.terminal_width < 20 {
+            return (self.terminal_width, 0)
+        }
+        let ratio = self.config.preview.width_ratio.clamp(0.1, 0.9);
+        let preview_width = (self.terminal_width as f32 * ratio) as usize;
+        // 区切り線の分
+        (self.terminal_width - preview_width - 1, preview_width)
+    }
+
+    // 表示開始位置の更新
+    fn update_display_size(&mut self) {
+        let display_height = self.get_display_height();
         
         // display_startの更新---------------------------------------
         if self.cursor_idx >= self.display_start_idx + display_height - 1 {
@@ -269,13 +625,59 @@ impl Viewer {
     }
 
     // 表示をおこなうメソッド -----------------------------------------
+    // 画面全体を1行ずつ組み立て，前回から変わった行だけを書き換える
     pub fn display(&mut self) -> Result<()> {
         self.update_display_size();
+        let display_height = self.get_display_height();
+        let mut frame: Vec<String> = Vec::with_capacity(self.terminal_height);
 
-        let node_map = self.node_map.lock().unwrap();
-        queue!(stdout(), Clear(ClearType::All), MoveTo(0, 0))?;
+        // パネルを開いているときはツリーの代わりに表示
+        if self.panel.is_some() {
+            frame.extend(self.render_panel());
+        } else {
+            let (tree_width, preview_width) = self.get_layout();
+            let tree = self.render_tree(tree_width)?;
+            if preview_width > 0 {
+                // ツリーの行を幅まで埋めてから右側にプレビューをつなげる
+                let preview = self.render_preview(preview_width)?;
+                for (row, preview_line) in preview.into_iter().enumerate() {
+                    let tree_line = tree.get(row).cloned().unwrap_or_default();
+                    let blank = " ".repeat(tree_width.saturating_sub(width::str_width(&tree_line)));
+                    frame.push(format!("{}{}{}", tree_line, blank, preview_line));
+                }
+            } else {
+                frame.extend(tree);
+            }
+        }
+        frame.resize(display_height, String::new());
+
+        // ステータス行 -----------------------------------------------------
+        if let Some(ref status_line) = self.status_line {
+            let line = truncate(status_line, self.terminal_width);
+            frame.push(format!("{}{}{}", COLOR::BOLD, line, COLOR::RESET));
+        }
 
-        // ノードの表示
+        // コンソールメッセージ ---------------------------------------------
+        if let Some(ref console_msg) = self.console_message {
+            let color = self.theme.message(match console_msg.status {
+                ConsoleMessageStatus::Info => "info",
+                ConsoleMessageStatus::Error => "error",
+                ConsoleMessageStatus::Notify => "notify",
+            });
+            // 綺麗に表示する用 (行ごとに端末の幅まで空白で埋める)
+            for line in width::wrap(&console_msg.message, self.terminal_width) {
+                let blank = " ".repeat(self.terminal_width.saturating_sub(width::str_width(&line)));
+                frame.push(format!("{}{}{}{}", color, line, blank, COLOR::RESET));
+            }
+        }
+
+        self.screen.draw(frame, self.terminal_width)
+    }
+
+    // ノードの行 ---------------------------------------------------
+    fn render_tree(&self, width: usize) -> Result<Vec<String>> {
+        let node_map = self.node_map.lock().unwrap();
+        let mut lines: Vec<String> = Vec::new();
         for i in self.display_start_idx..=self.display_end_idx {
             
             let id = self.id_list[i];
@@ -286,52 +688,89 @@ impl Viewer {
             let icon = match node_type {
                 NodeType::Folder => {
                     let is_open = node_map.get_is_open(&id)?;
-                    icon::get_folder_icon(is_open, self.config.nerd_font)
+                    icon::get_folder_icon(is_open, self.config.nerd_font, &self.theme)
                 }
                 NodeType::File => {
-                    icon::get_file_icon(&name, self.config.nerd_font)
+                    icon::get_file_icon(&name, self.config.nerd_font, &self.theme)
                 }
                 NodeType::Unknown => {
                     String::from("?")
                 }
             };
-            let color = self.get_line_color(i);
+            let git_marker = self.get_git_marker(&node_map.get_path(&id)?);
+            // 印の分だけ名前を短くする
+            let name_width = if git_marker.is_some() { width.saturating_sub(2) } else { width };
 
-            let line = self.format(name, icon, rank, color);
-            let out = format!("{}{}{}", COLOR::RESET, line, COLOR::RESET);
+            let line = self.format(i, name, icon, rank, name_width);
+            lines.push(format!("{}{}{}{}", COLOR::RESET, line, git_marker.unwrap_or_default(), COLOR::RESET));
+        }
+        Ok(lines)
+    }
+
+    // プレビューの行 (ツリーの右側) -------------------------------------
+    fn render_preview(&mut self, width: usize) -> Result<Vec<String>> {
+        let display_height = self.get_display_height();
+        let path = {
+            let node_map = self.node_map.lock().unwrap();
+            node_map.get_path(&self.get_cursor_id())?
+        };
 
-            queue!(stdout(), Print(out), MoveToNextLine(1))?;
+        // 同じファイルが更新されていなければ読み直さない
+        let modified = path.symlink_metadata().and_then(|m| m.modified()).ok();
+        let cached = match self.preview_cache {
+            Some((ref p, m, _)) => *p == path && m == modified,
+            None => false,
+        };
+        if !cached {
+            let lines = preview::build(&path, self.config.preview.max_bytes, self.terminal_height);
+            self.preview_cache = Some((path, modified, lines));
         }
+        let lines = match self.preview_cache {
+            Some((_, _, ref lines)) => lines,
+            None => return Ok(Vec::new()),
+        };
 
-        // コンソールメッセージ ---------------------------------------------
-        if let Some(ref console_msg) = self.console_message {
-            let message = console_msg.message.clone();
-            let status = console_msg.status.clone();
-
-            // 綺麗に表示する用
-            let num_line = console_msg.get_num_lines(self.terminal_width);
-            let blank = if message.len().rem_euclid(self.terminal_width) != 0 {
-                String::from(" ").repeat(
-                    self.terminal_width - message.len().rem_euclid(self.terminal_width)
-                )
-            } else {
-                String::from("")
-            };
-            let color = match status{
-                ConsoleMessageStatus::Info => COLOR::back::BLUE,
-                ConsoleMessageStatus::Error => COLOR::back::RED,
-                ConsoleMessageStatus::Notify => COLOR::back::GREEN,
-            };
-            
-            queue!(
-                stdout(), 
-                MoveTo(0, (self.terminal_height - num_line - 1) as u16), 
-                Clear(ClearType::FromCursorDown), 
-                MoveDown(1), 
-                Print(format!("{}{}{}{}", color, message, blank, COLOR::RESET))
-            )?;
-        }
-        stdout().flush()?;
-        Ok(())
+        Ok((0..display_height).map(|row| {
+            let line = lines.get(row).map(|l| truncate(l, width.saturating_sub(1))).unwrap_or_default();
+            format!("│ {}", line)
+        }).collect())
+    }
+
+    // パネルの行 (1行目はタイトル) -------------------------------------
+    fn render_panel(&mut self) -> Vec<String> {
+        let display_height = self.get_display_height();
+        let width = self.terminal_width;
+        let panel = match self.panel {
+            Some(ref mut panel) => panel,
+            None => return Vec::new(),
+        };
+
+        // カーソルが見える位置までずらす
+        let rows = display_height.saturating_sub(2).max(1);
+        if panel.cursor < panel.start {
+            panel.start = panel.cursor;
+        } else if panel.cursor >= panel.start + rows {
+            panel.start = panel.cursor + 1 - rows;
+        }
+
+        let mut lines: Vec<String> = Vec::new();
+        let title = truncate(&panel.title, width);
+        lines.push(format!("{}{}{}", COLOR::BOLD, title, COLOR::RESET));
+        if panel.items.is_empty() {
+            lines.push(String::from("  (empty)"));
+        }
+        let end = (panel.start + rows).min(panel.items.len());
+        for i in panel.start..end {
+            let color = if i == panel.cursor { self.theme.cursor() } else { COLOR::RESET };
+            let line = truncate(&format!("  {}", panel.items[i]), width);
+            lines.push(format!("{}{}{}", color, line, COLOR::RESET));
+        }
+        lines
     }
 }
+
+// 端末の幅に収まるように切り詰める
+// 最後の桁は空けておく
+fn truncate(line: &str, width: usize) -> String {
+    width::truncate(line, width.saturating_sub(1))
+}
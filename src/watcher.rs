@@ -0,0 +1,110 @@
+use anyhow::Result;
+use notify::event::{EventKind, ModifyKind, RenameMode};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+
+// ファイルシステムの変更をNodeMapで扱いやすい形にしたもの
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsChange {
+    Created(PathBuf),
+    Removed(PathBuf),
+    Modified(PathBuf),
+    Rescan,
+}
+
+// ----------------------------------------------------------------
+// 開いているフォルダのみを非再帰で監視する
+// ----------------------------------------------------------------
+#[derive(Debug)]
+pub struct FsWatcher {
+    watcher: RecommendedWatcher,
+    receiver: Receiver<notify::Result<Event>>,
+    watched: HashSet<PathBuf>,
+}
+
+impl FsWatcher {
+    pub fn new() -> Result<FsWatcher> {
+        let (sender, receiver) = channel();
+        let watcher = notify::recommended_watcher(move |event| {
+            let _ = sender.send(event);
+        })?;
+
+        Ok(FsWatcher {
+            watcher,
+            receiver,
+            watched: HashSet::new(),
+        })
+    }
+
+    pub fn watch(&mut self, path: &Path) -> Result<()> {
+        if self.watched.contains(path) {
+            return Ok(());
+        }
+        self.watcher.watch(path, RecursiveMode::NonRecursive)?;
+        self.watched.insert(path.to_path_buf());
+        log::info!("Watch: {:?}", path);
+        Ok(())
+    }
+
+    // 既に消えているパスのunwatchは失敗するので無視
+    pub fn unwatch(&mut self, path: &Path) {
+        if self.watched.remove(path) {
+            let _ = self.watcher.unwatch(path);
+            log::info!("Unwatch: {:?}", path);
+        }
+    }
+
+    // 溜まっているイベントを全て取り出す (ブロックしない)
+    pub fn poll(&self) -> Vec<FsChange> {
+        let mut changes: Vec<FsChange> = Vec::new();
+        while let Ok(event) = self.receiver.try_recv() {
+            match event {
+                Ok(event) => changes.extend(Self::convert(event)),
+                Err(e) => {
+                    log::warn!("Watch error: {:?}", e);
+                    changes.push(FsChange::Rescan);
+                }
+            }
+        }
+        changes
+    }
+
+    fn convert(event: Event) -> Vec<FsChange> {
+        if event.need_rescan() {
+            return vec![FsChange::Rescan];
+        }
+
+        let paths = event.paths;
+        match event.kind {
+            EventKind::Create(_) => paths.into_iter().map(FsChange::Created).collect(),
+            EventKind::Remove(_) => paths.into_iter().map(FsChange::Removed).collect(),
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+                paths.into_iter().map(FsChange::Removed).collect()
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+                paths.into_iter().map(FsChange::Created).collect()
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                let mut changes = Vec::new();
+                let mut iter = paths.into_iter();
+                if let Some(from) = iter.next() {
+                    changes.push(FsChange::Removed(from));
+                }
+                if let Some(to) = iter.next() {
+                    changes.push(FsChange::Created(to));
+                }
+                changes
+            }
+            // どちら向きか分からない名前の変更は実際に存在するかで判断
+            EventKind::Modify(ModifyKind::Name(_)) => paths
+                .into_iter()
+                .map(|p| if p.exists() { FsChange::Created(p) } else { FsChange::Removed(p) })
+                .collect(),
+            EventKind::Modify(_) => paths.into_iter().map(FsChange::Modified).collect(),
+            EventKind::Access(_) => Vec::new(),
+            EventKind::Any | EventKind::Other => vec![FsChange::Rescan],
+        }
+    }
+}
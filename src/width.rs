@@ -0,0 +1,153 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+// ----------------------------------------------------------------
+// 端末での表示幅 (バイト数や文字数ではなく桁数) を扱う
+// 全角文字や絵文字は2桁，結合文字は0桁として，書記素 (見た目の1文字) の途中では切らない
+// ----------------------------------------------------------------
+
+// 1書記素の幅 (ZWJでつないだ絵文字も1つの絵文字として2桁にする)
+fn grapheme_width(grapheme: &str) -> usize {
+    grapheme.width().min(2)
+}
+
+// 色をつけるエスケープシーケンスを除いた表示幅
+pub fn str_width(s: &str) -> usize {
+    strip_ansi(s).graphemes(true).map(grapheme_width).sum()
+}
+
+// widthに収まらなければ末尾を "…" にして切り詰める
+pub fn truncate(s: &str, width: usize) -> String {
+    if str_width(s) <= width {
+        return s.to_string()
+    }
+    if width == 0 {
+        return String::new()
+    }
+
+    // "…" の1桁を残して詰める
+    let mut truncated = String::new();
+    let mut current = 0;
+    for grapheme in s.graphemes(true) {
+        let w = grapheme_width(grapheme);
+        if current + w > width - 1 {
+            break;
+        }
+        truncated.push_str(grapheme);
+        current += w;
+    }
+    truncated.push('…');
+    truncated
+}
+
+// 幅widthの端末で折り返したときの各行
+// 行末に入りきらない全角文字は端末と同じように次の行に送る
+pub fn wrap(s: &str, width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    if width == 0 {
+        return lines
+    }
+    let mut line = String::new();
+    let mut current = 0;
+    for grapheme in s.graphemes(true) {
+        let w = grapheme_width(grapheme);
+        if current + w > width {
+            lines.push(std::mem::take(&mut line));
+            current = 0;
+        }
+        line.push_str(grapheme);
+        current += w;
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+// "\x1b[...m" を取り除く
+pub fn strip_ansi(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            for c in chars.by_ref() {
+                if c == 'm' {
+                    break;
+                }
+            }
+            continue;
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn width_of_ascii_cjk_and_emoji() {
+        assert_eq!(str_width("main.rs"), 7);
+        assert_eq!(str_width("日本語.txt"), 10);
+        assert_eq!(str_width("📁"), 2);
+        // ZWJでつないだ家族の絵文字は1文字
+        assert_eq!(str_width("👨\u{200d}👩\u{200d}👧"), 2);
+    }
+
+    #[test]
+    fn combining_marks_have_no_width() {
+        // "e" + 結合アキュート
+        assert_eq!(str_width("cafe\u{301}"), 4);
+        // "か" + 結合濁点
+        assert_eq!(str_width("か\u{3099}"), 2);
+    }
+
+    #[test]
+    fn escape_sequences_have_no_width() {
+        assert_eq!(str_width("\x1b[33m\u{f115}\x1b[0m"), 1);
+        assert_eq!(strip_ansi("\x1b[1m\x1b[34mabc\x1b[0m"), "abc");
+    }
+
+    #[test]
+    fn truncate_keeps_short_names() {
+        assert_eq!(truncate("abc", 3), "abc");
+        assert_eq!(truncate("日本", 4), "日本");
+    }
+
+    #[test]
+    fn truncate_ascii() {
+        assert_eq!(truncate("abcdef", 4), "abc…");
+        assert_eq!(truncate("abcdef", 1), "…");
+        assert_eq!(truncate("abcdef", 0), "");
+    }
+
+    #[test]
+    fn truncate_cjk_does_not_overflow() {
+        // 2桁の文字が入りきらないときは空けておく
+        assert_eq!(truncate("日本語ファイル", 6), "日本…");
+        assert_eq!(truncate("日本語ファイル", 7), "日本語…");
+        assert!(str_width(&truncate("日本語ファイル", 6)) <= 6);
+    }
+
+    #[test]
+    fn truncate_does_not_split_graphemes() {
+        assert_eq!(truncate("cafe\u{301}s_menu", 5), "cafe\u{301}…");
+        assert_eq!(truncate("ab👨\u{200d}👩\u{200d}👧cd", 4), "ab…");
+        assert_eq!(truncate("ab👨\u{200d}👩\u{200d}👧cd", 5), "ab👨\u{200d}👩\u{200d}👧…");
+    }
+
+    #[test]
+    fn wrap_by_width() {
+        assert_eq!(wrap("abcdef", 4), vec!["abcd", "ef"]);
+        assert_eq!(wrap("abcd", 4), vec!["abcd"]);
+        assert!(wrap("", 4).is_empty());
+    }
+
+    #[test]
+    fn wrap_moves_wide_characters_to_next_line() {
+        assert_eq!(wrap("abc日本", 4), vec!["abc", "日本"]);
+        assert_eq!(wrap("日本語", 5), vec!["日本", "語"]);
+        assert_eq!(wrap("a😀b", 2), vec!["a", "😀", "b"]);
+    }
+}